@@ -1,5 +1,10 @@
-use clap::{Parser, ValueEnum};
-use reg_core::{run, run_from_json, DiffImageFormat, JsonReport, Options, Url};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use reg_core::{
+    compare_files, parse_hex_color, run, run_from_json, write_output, AlphaMode, Artifact,
+    CleanDiffDir, DiffImageFormat, DiffStyle, ErrorInfo, ImageFormat, JsonReport, Options,
+    PairResult, PathMapping, Url,
+};
+use serde::Serialize;
 use std::path::{Path, PathBuf};
 use tracing::info_span;
 
@@ -18,6 +23,111 @@ impl From<DiffFormatArg> for DiffImageFormat {
     }
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CleanDiffDirArg {
+    Before,
+    After,
+}
+
+impl From<CleanDiffDirArg> for CleanDiffDir {
+    fn from(c: CleanDiffDirArg) -> Self {
+        match c {
+            CleanDiffDirArg::Before => CleanDiffDir::Before,
+            CleanDiffDirArg::After => CleanDiffDir::After,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum AlphaArg {
+    Keep,
+    Flatten,
+    Ignore,
+}
+
+/// Alpha / colour-profile handling, shared by the directory run and
+/// `compare-file`.
+#[derive(ClapArgs, Debug)]
+struct ColorArgs {
+    /// How to treat transparency before diffing: keep RGBA as is, flatten
+    /// onto `--alphaBackground`, or ignore alpha entirely.
+    #[arg(long = "alpha", value_enum)]
+    alpha: Option<AlphaArg>,
+
+    /// Background for `--alpha flatten`, as #rgb or #rrggbb (default #ffffff).
+    #[arg(long = "alphaBackground")]
+    alpha_background: Option<String>,
+
+    /// Convert images with an embedded ICC profile to sRGB before diffing.
+    #[arg(long = "normalizeSrgb", default_missing_value = "true", num_args = 0..=1)]
+    normalize_srgb: Option<bool>,
+}
+
+impl ColorArgs {
+    fn alpha_mode(&self) -> Result<Option<AlphaMode>, reg_core::CompareError> {
+        Ok(match self.alpha {
+            None => None,
+            Some(AlphaArg::Keep) => Some(AlphaMode::Keep),
+            Some(AlphaArg::Ignore) => Some(AlphaMode::Ignore),
+            Some(AlphaArg::Flatten) => Some(AlphaMode::Flatten(
+                self.alpha_background
+                    .as_deref()
+                    .map(parse_hex_color)
+                    .transpose()?
+                    .unwrap_or([255, 255, 255]),
+            )),
+        })
+    }
+}
+
+/// Diff image styling, shared by the directory run and `compare-file`.
+#[derive(ClapArgs, Debug)]
+struct DiffStyleArgs {
+    /// Colour for changed pixels in diff images, as #rgb or #rrggbb
+    /// (default #ff0000).
+    #[arg(long = "diffHighlight")]
+    diff_highlight: Option<String>,
+
+    /// How far unchanged pixels fade toward white in diff images, 0 to 1
+    /// (default 0.9).
+    #[arg(long = "diffDim")]
+    diff_dim: Option<f32>,
+
+    /// Write diff images as expected | actual | diff in one strip.
+    #[arg(long = "diffSideBySide", default_missing_value = "true", num_args = 0..=1)]
+    diff_side_by_side: Option<bool>,
+}
+
+impl DiffStyleArgs {
+    /// `None` unless a styling flag was given, so the diff engine's own
+    /// image is kept by default.
+    fn diff_style(&self) -> Result<Option<DiffStyle>, reg_core::CompareError> {
+        if self.diff_highlight.is_none()
+            && self.diff_dim.is_none()
+            && self.diff_side_by_side.is_none()
+        {
+            return Ok(None);
+        }
+        let default = DiffStyle::default();
+        if let Some(dim) = self.diff_dim.filter(|d| !(0.0..=1.0).contains(d)) {
+            return Err(reg_core::CompareError::InvalidOption {
+                option: "diffDim",
+                reason: format!("expected a value between 0 and 1, got {dim}"),
+            });
+        }
+        Ok(Some(DiffStyle {
+            highlight: self
+                .diff_highlight
+                .as_deref()
+                .map(parse_hex_color)
+                .transpose()?
+                .unwrap_or(default.highlight),
+            dim: self.diff_dim.unwrap_or(default.dim),
+            side_by_side: self.diff_side_by_side.unwrap_or(default.side_by_side),
+        }))
+    }
+}
+
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 enum AdditionalDetection {
     None,
@@ -25,8 +135,11 @@ enum AdditionalDetection {
 }
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[clap(index = 1)]
     actual_dir: Option<PathBuf>,
 
@@ -42,10 +155,128 @@ struct Args {
     #[arg(short = 'J', long)]
     json: Option<PathBuf>,
 
+    /// JSON or CSV list of `{name, actual, expected}` pairs to compare
+    /// instead of mirroring the actual/expected directories. Relative paths
+    /// resolve against the positional actual/expected dirs.
+    #[arg(long = "manifest")]
+    manifest: Option<PathBuf>,
+
+    /// Rewrite an actual-relative path before looking up its expected
+    /// image, as `regex=>replacement` (e.g. `^(.*)\.png$=>$1@chrome.png`).
+    /// Repeatable; the first matching rule wins.
+    #[arg(long = "pathMapping")]
+    path_mapping: Vec<String>,
+
+    /// Report a deleted image and a new image with matching contents as a
+    /// single renamed item.
+    #[arg(long = "detectRenames", default_missing_value = "true", num_args = 0..=1)]
+    detect_renames: Option<bool>,
+
+    /// Descend into symlinked directories when walking actual/expected.
+    #[arg(long = "followSymlinks", default_missing_value = "true", num_args = 0..=1)]
+    follow_symlinks: Option<bool>,
+
+    /// Ignore files and directories whose name starts with `.`.
+    #[arg(long = "skipHidden", default_missing_value = "true", num_args = 0..=1)]
+    skip_hidden: Option<bool>,
+
+    /// Maximum directory depth to walk below actual/expected (0 = top level only).
+    #[arg(long = "maxDepth")]
+    max_depth: Option<usize>,
+
+    /// Treat a missing expected directory as empty (every image is new)
+    /// instead of failing. Useful for the first run that creates baselines.
+    #[arg(long = "allowEmptyExpected", default_missing_value = "true", num_args = 0..=1)]
+    allow_empty_expected: Option<bool>,
+
+    /// Pair actual/expected paths that differ only by letter case or
+    /// Unicode normalisation (NFC vs NFD).
+    #[arg(long = "caseInsensitivePaths", default_missing_value = "true", num_args = 0..=1)]
+    case_insensitive_paths: Option<bool>,
+
+    /// Comma-separated image formats to pick up (e.g. `png,jpg,svg`).
+    /// Defaults to the classic set: png, jpg, gif, bmp, tiff, webp.
+    #[arg(long = "formats", value_delimiter = ',')]
+    formats: Vec<String>,
+
+    /// Scale factor for rasterising SVG images (default 1).
+    #[arg(long = "svgScale")]
+    svg_scale: Option<f32>,
+
+    /// List files skipped for their extension as `unsupportedItems` in
+    /// reg.json instead of ignoring them.
+    #[arg(long = "reportUnsupported", default_missing_value = "true", num_args = 0..=1)]
+    report_unsupported: Option<bool>,
+
+    /// Classify files by content (magic bytes) instead of extension and
+    /// warn when the two disagree.
+    #[arg(long = "sniffContent", default_missing_value = "true", num_args = 0..=1)]
+    sniff_content: Option<bool>,
+
+    /// Compare animated GIF/APNG/WebP frame by frame instead of only the
+    /// first frame.
+    #[arg(long = "compareFrames", default_missing_value = "true", num_args = 0..=1)]
+    compare_frames: Option<bool>,
+
+    #[command(flatten)]
+    color: ColorArgs,
+
     /// Path to write the JUnit XML test report.
     #[arg(long = "junit")]
     junit: Option<PathBuf>,
 
+    /// Mustache template to render the HTML report with instead of the
+    /// built-in one.
+    #[arg(long = "template")]
+    template: Option<PathBuf>,
+
+    /// Write the report's JS/CSS/favicon as content-hashed files into this
+    /// directory and link them instead of inlining them.
+    #[arg(long = "reportAssets")]
+    report_assets: Option<PathBuf>,
+
+    /// Embed actual/expected/diff images in the HTML report so it works as
+    /// a single file.
+    #[arg(long = "embedImages", default_missing_value = "true", num_args = 0..=1)]
+    embed_images: Option<bool>,
+
+    /// With `--embedImages`, embed images larger than this (longest side, in
+    /// pixels) as thumbnails; full size loads from the image directories.
+    #[arg(long = "embedThumbnailSize", value_parser = clap::value_parser!(u32).range(1..))]
+    embed_thumbnail_size: Option<u32>,
+
+    /// Also write a portable copy of the report (HTML, reg.json and the
+    /// images it lists) to this directory, or to a `.tar` / `.zip` archive.
+    #[arg(long = "bundle")]
+    bundle: Option<PathBuf>,
+
+    /// Inline at most this many passed items in the HTML report (0 omits
+    /// them); reg.json keeps them all.
+    #[arg(long = "reportPassedLimit")]
+    report_passed_limit: Option<usize>,
+
+    /// Inline at most this many passed, renamed and tolerated items in the
+    /// HTML report and write the rest to JSON pages next to it. Failed, new
+    /// and deleted items are always inlined.
+    #[arg(
+        long = "reportPageSize",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    report_page_size: Option<usize>,
+
+    /// Write thumbnails of the report's images into this directory and
+    /// show them in the report instead of full-size images.
+    #[arg(long = "thumbnails")]
+    thumbnails: Option<PathBuf>,
+
+    /// Longest side of thumbnails in pixels (default 320).
+    #[arg(long = "thumbnailSize", value_parser = clap::value_parser!(u32).range(1..))]
+    thumbnail_size: Option<u32>,
+
+    /// Thumbnail format (webp or png; default webp).
+    #[arg(long = "thumbnailFormat", value_enum)]
+    thumbnail_format: Option<DiffFormatArg>,
+
     /// Escalate added/deleted images to failures in the JUnit XML (and at
     /// the CLI exit-code layer on the JS wrapper). Mirrors classic
     /// reg-cli's `-E, --extendedErrors`.
@@ -64,6 +295,8 @@ struct Args {
     #[arg(short = 'P', long = "urlPrefix")]
     url_prefix: Option<Url>,
 
+    /// Number of diff worker threads. Defaults to a count derived from the
+    /// suite's total pixel area, capped at the available CPU cores.
     #[arg(short = 'C', long)]
     concurrency: Option<usize>,
 
@@ -75,6 +308,21 @@ struct Args {
     #[arg(long = "diffFormat", value_enum)]
     diff_format: Option<DiffFormatArg>,
 
+    #[command(flatten)]
+    diff_style: DiffStyleArgs,
+
+    /// Also write diff images for items that pass within
+    /// `--thresholdPixel` / `--thresholdRate`, listed as `toleratedItems`.
+    #[arg(long = "writeToleratedDiffs", default_missing_value = "true", num_args = 0..=1)]
+    write_tolerated_diffs: Option<bool>,
+
+    /// Remove diff images left by the previous run, either before comparing
+    /// or after (only those not rewritten). Only files listed in the diff
+    /// dir's `.reg-cli-diffs.json`, which runs with this flag write, are
+    /// removed.
+    #[arg(long = "cleanDiffDir", value_enum)]
+    clean_diff_dir: Option<CleanDiffDirArg>,
+
     /// Re-render HTML report from an existing reg.json (no image comparison).
     /// Mirrors classic reg-cli's `-F, --from`.
     #[arg(short = 'F', long = "from")]
@@ -86,11 +334,98 @@ struct Args {
     additional_detection: Option<AdditionalDetection>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare two image files directly and print the result as JSON. Exits
+    /// with status 1 when the images differ.
+    #[command(name = "compare-file")]
+    CompareFile(CompareFileArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CompareFileArgs {
+    actual: PathBuf,
+
+    expected: PathBuf,
+
+    /// Write the diff image here when the images differ.
+    #[arg(short = 'D', long = "diff")]
+    diff: Option<PathBuf>,
+
+    #[arg(short = 'M', long = "matchingThreshold")]
+    matching_threshold: Option<f32>,
+
+    #[arg(short = 'T', long = "thresholdRate")]
+    threshold_rate: Option<f32>,
+
+    #[arg(short = 'S', long = "thresholdPixel")]
+    threshold_pixel: Option<u64>,
+
+    #[arg(short = 'A', long = "enableAntialias", default_missing_value = "true", num_args = 0..=1)]
+    enable_antialias: Option<bool>,
+
+    #[arg(long = "diffFormat", value_enum)]
+    diff_format: Option<DiffFormatArg>,
+
+    #[command(flatten)]
+    diff_style: DiffStyleArgs,
+
+    /// Scale factor for rasterising SVG inputs (default 1).
+    #[arg(long = "svgScale")]
+    svg_scale: Option<f32>,
+
+    /// Compare animated inputs frame by frame.
+    #[arg(long = "compareFrames", default_missing_value = "true", num_args = 0..=1)]
+    compare_frames: Option<bool>,
+
+    #[command(flatten)]
+    color: ColorArgs,
+}
+
+/// What `inner` hands back to `wasm_main`: a full report for directory /
+/// `--from` runs, or a single pair result for `compare-file`. Failures are
+/// returned as `{"error": {"code", "stage", "message", "path"}}`.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Output {
+    Report(Box<JsonReport>),
+    Pair(PairResult),
+    // Only built by `wasm_main`; the native binary prints errors instead.
+    #[cfg_attr(not(all(target_os = "wasi", target_env = "p1")), allow(dead_code))]
+    Error { error: ErrorInfo },
+}
+
 #[cfg(not(all(target_os = "wasi", target_env = "p1")))]
 pub fn main() {
     // Initialize tracing for non-WASI builds
     reg_core::init_tracing();
-    let _ = inner();
+    match inner().and_then(print_output) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("reg-cli: error[{}]: {e}", e.code());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print what the native binary shows on stdout and report whether the
+/// process should exit successfully. Directory runs write their outputs to
+/// files; `compare-file` prints its result and fails when the images differ.
+/// Under Wasm the same payload goes back to the host from `wasm_main`.
+#[cfg(not(all(target_os = "wasi", target_env = "p1")))]
+fn print_output(output: Output) -> Result<bool, reg_core::CompareError> {
+    let Output::Pair(res) = output else {
+        return Ok(true);
+    };
+    let body = serde_json::to_string_pretty(&res).map_err(|source| {
+        reg_core::CompareError::Serialize {
+            artifact: Artifact::Json,
+            source,
+        }
+    })?;
+    println!("{body}");
+    Ok(res.passed)
 }
 
 #[cfg(all(target_os = "wasi", target_env = "p1"))]
@@ -98,15 +433,45 @@ pub fn main() {
     // NOP
 }
 
-fn inner() -> Result<JsonReport, reg_core::CompareError> {
+fn inner() -> Result<Output, reg_core::CompareError> {
     let _root_span = info_span!("reg_cli_main").entered();
 
     let args = Args::parse();
 
+    if let Some(Command::CompareFile(cf)) = args.command {
+        return compare_file(cf).map(Output::Pair);
+    }
+
+    let path_mappings = args
+        .path_mapping
+        .iter()
+        .map(|rule| PathMapping::parse(rule))
+        .collect::<Result<Vec<_>, _>>()?;
+    let formats = args
+        .formats
+        .iter()
+        .map(|name| ImageFormat::parse(name))
+        .collect::<Result<Vec<_>, _>>()?;
+
     let options = Options {
         report: args.report.as_deref().map(Path::new),
         junit_report: args.junit.as_deref().map(Path::new),
         json: args.json.as_deref().map(Path::new),
+        manifest: args.manifest.as_deref().map(Path::new),
+        path_mappings: Some(&path_mappings),
+        detect_renames: args.detect_renames,
+        follow_symlinks: args.follow_symlinks,
+        skip_hidden: args.skip_hidden,
+        max_depth: args.max_depth,
+        allow_empty_expected: args.allow_empty_expected,
+        case_insensitive_paths: args.case_insensitive_paths,
+        formats: (!formats.is_empty()).then_some(formats.as_slice()),
+        svg_scale: args.svg_scale,
+        report_unsupported: args.report_unsupported,
+        sniff_content: args.sniff_content,
+        compare_frames: args.compare_frames,
+        alpha_mode: args.color.alpha_mode()?,
+        normalize_srgb: args.color.normalize_srgb,
         extended_errors: args.extended_errors,
         matching_threshold: args.matching_threshold,
         threshold_rate: args.threshold_rate,
@@ -115,6 +480,19 @@ fn inner() -> Result<JsonReport, reg_core::CompareError> {
         enable_antialias: args.enable_antialias,
         url_prefix: args.url_prefix,
         diff_image_format: args.diff_format.map(DiffImageFormat::from),
+        diff_style: args.diff_style.diff_style()?,
+        write_tolerated_diffs: args.write_tolerated_diffs,
+        clean_diff_dir: args.clean_diff_dir.map(CleanDiffDir::from),
+        template: args.template.as_deref(),
+        report_assets_dir: args.report_assets.as_deref(),
+        embed_images: args.embed_images,
+        embed_thumbnail_size: args.embed_thumbnail_size,
+        bundle: args.bundle.as_deref(),
+        report_passed_limit: args.report_passed_limit,
+        report_page_size: args.report_page_size,
+        thumbnail_dir: args.thumbnails.as_deref(),
+        thumbnail_size: args.thumbnail_size,
+        thumbnail_format: args.thumbnail_format.map(DiffImageFormat::from),
         enable_client_additional_detection: args
             .additional_detection
             .map(|v| matches!(v, AdditionalDetection::Client)),
@@ -123,26 +501,50 @@ fn inner() -> Result<JsonReport, reg_core::CompareError> {
     // `-F / --from` short-circuits the diff pipeline and re-renders HTML from
     // an existing reg.json. Positional dirs are not required in this mode.
     if let Some(from) = args.from.as_deref() {
-        return run_from_json(from, options);
+        return run_from_json(from, options).map(|r| Output::Report(Box::new(r)));
     }
 
+    let missing = |option, reason: &str| reg_core::CompareError::InvalidOption {
+        option,
+        reason: reason.to_string(),
+    };
     let actual_dir = args.actual_dir.ok_or_else(|| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "actual_dir is required (or pass --from to regenerate from reg.json)",
-        )
-    })?;
-    let expected_dir = args.expected_dir.ok_or_else(|| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "expected_dir is required",
+        missing(
+            "actual_dir",
+            "required (or pass --from to regenerate from reg.json)",
         )
     })?;
-    let diff_dir = args.diff_dir.ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::InvalidInput, "diff_dir is required")
-    })?;
+    let expected_dir = args
+        .expected_dir
+        .ok_or_else(|| missing("expected_dir", "required"))?;
+    let diff_dir = args
+        .diff_dir
+        .ok_or_else(|| missing("diff_dir", "required"))?;
+
+    run(actual_dir, expected_dir, diff_dir, options).map(|r| Output::Report(Box::new(r)))
+}
 
-    run(actual_dir, expected_dir, diff_dir, options)
+fn compare_file(args: CompareFileArgs) -> Result<PairResult, reg_core::CompareError> {
+    let _span = info_span!("compare_file").entered();
+    let options = Options {
+        matching_threshold: args.matching_threshold,
+        threshold_rate: args.threshold_rate,
+        threshold_pixel: args.threshold_pixel,
+        enable_antialias: args.enable_antialias,
+        diff_image_format: args.diff_format.map(DiffImageFormat::from),
+        diff_style: args.diff_style.diff_style()?,
+        svg_scale: args.svg_scale,
+        compare_frames: args.compare_frames,
+        alpha_mode: args.color.alpha_mode()?,
+        normalize_srgb: args.color.normalize_srgb,
+        ..Options::default()
+    };
+    let res = compare_files(&args.actual, &args.expected, options)?;
+
+    if let (Some(diff_path), Some(diff_image)) = (args.diff.as_deref(), res.diff_image.as_deref()) {
+        write_output(Artifact::DiffImage, diff_path, diff_image)?;
+    }
+    Ok(res)
 }
 
 #[cfg(all(target_os = "wasi", target_env = "p1"))]
@@ -191,20 +593,30 @@ pub unsafe extern "C" fn set_trace_context(
 #[cfg(all(target_os = "wasi", target_env = "p1"))]
 #[no_mangle]
 pub extern "C" fn wasm_main() -> *mut WasmOutput {
-    let res = inner();
-    if let Ok(res) = res {
-        let mut s = serde_json::to_string_pretty(&res).unwrap();
-
-        let len = s.len();
-        let ptr = s.as_mut_ptr();
-        std::mem::forget(s);
+    // Never panic here: with `panic = "abort"` that would take the host
+    // process down with it. Errors go back to the host as a payload.
+    let error_output = |e: &reg_core::CompareError| {
+        eprintln!("Failed to exec wasm main. error[{}]: {e}", e.code());
+        Output::Error {
+            error: ErrorInfo::from(e),
+        }
+    };
+    let res = inner().unwrap_or_else(|e| error_output(&e));
+    let mut s = serde_json::to_string_pretty(&res)
+        .or_else(|source| {
+            serde_json::to_string_pretty(&error_output(&reg_core::CompareError::Serialize {
+                artifact: Artifact::Json,
+                source,
+            }))
+        })
+        .unwrap_or_default();
+
+    let len = s.len();
+    let ptr = s.as_mut_ptr();
+    std::mem::forget(s);
 
-        let output = Box::new(WasmOutput { len, buf: ptr });
-        Box::into_raw(output)
-    } else {
-        eprintln!("Failed to exec wasm main. Error details: {:?}", res);
-        panic!("Failed to exec wasm main. reason: {:?}", res);
-    }
+    let output = Box::new(WasmOutput { len, buf: ptr });
+    Box::into_raw(output)
 }
 
 /// Get collected trace data as JSON string
@@ -0,0 +1,310 @@
+//! Frame-by-frame decoding for animated GIF, APNG and WebP.
+//!
+//! `image-diff-rs` only ever looks at the first frame of an animation. With
+//! `Options::compare_frames`, animated inputs are decoded here into fully
+//! composited RGBA frames (disposal and blending applied, as a viewer would
+//! show them), each re-encoded as PNG so the diff engine can take them one
+//! at a time.
+//!
+//! Every frame of both sides is held in memory at once, so very long or
+//! very large animations are expensive; that's the price of opting in.
+
+use std::io::Cursor;
+
+use crate::{
+    format::{self, ImageFormat},
+    CompareError,
+};
+
+/// PNG-encoded frames of `buf` when it's an animation with more than one
+/// frame. `None` for still images and for formats that can't animate, so
+/// the caller can fall back to a plain single-image diff.
+pub(crate) fn frames(buf: &[u8]) -> Result<Option<Vec<Vec<u8>>>, CompareError> {
+    let format = format::sniff(buf);
+    let decoded = match format {
+        Some(ImageFormat::Gif) => gif_frames(buf),
+        Some(ImageFormat::Png) => apng_frames(buf),
+        Some(ImageFormat::Webp) => webp_frames(buf),
+        _ => return Ok(None),
+    };
+    let decode_error = |reason: String| CompareError::Decode {
+        format: format.map_or("image", ImageFormat::name),
+        path: None,
+        reason,
+    };
+    let Some(canvases) = decoded.map_err(decode_error)? else {
+        return Ok(None);
+    };
+    if canvases.len() < 2 {
+        return Ok(None);
+    }
+    canvases
+        .iter()
+        .map(|c| c.encode_png().map_err(decode_error))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// A full-size RGBA8 frame.
+struct Canvas {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Blend {
+    /// Overwrite the region, alpha included.
+    Source,
+    /// Alpha-composite onto what's there.
+    Over,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            rgba: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Draw an RGBA8 `src` of `w`×`h` at (`x`, `y`), clipped to the canvas.
+    fn draw(&mut self, x: u32, y: u32, w: u32, h: u32, src: &[u8], blend: Blend) {
+        for row in 0..h.min(self.height.saturating_sub(y)) {
+            for col in 0..w.min(self.width.saturating_sub(x)) {
+                let s = (row as usize * w as usize + col as usize) * 4;
+                let d = ((y + row) as usize * self.width as usize + (x + col) as usize) * 4;
+                let (Some(src), Some(dst)) = (src.get(s..s + 4), self.rgba.get_mut(d..d + 4))
+                else {
+                    continue;
+                };
+                match blend {
+                    Blend::Source => dst.copy_from_slice(src),
+                    Blend::Over => over(dst, src),
+                }
+            }
+        }
+    }
+
+    /// Reset a region to transparent black.
+    fn clear(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        let transparent = vec![0; w as usize * h as usize * 4];
+        self.draw(x, y, w, h, &transparent, Blend::Source);
+    }
+
+    fn snapshot(&self) -> Canvas {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            rgba: self.rgba.clone(),
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&self.rgba)
+            .map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+}
+
+/// Non-premultiplied source-over for one RGBA8 pixel.
+fn over(dst: &mut [u8], src: &[u8]) {
+    let sa = u32::from(src[3]);
+    if sa == 255 {
+        dst.copy_from_slice(src);
+        return;
+    }
+    if sa == 0 {
+        return;
+    }
+    let da = u32::from(dst[3]) * (255 - sa) / 255;
+    let out_a = sa + da;
+    for i in 0..3 {
+        let c = (u32::from(src[i]) * sa + u32::from(dst[i]) * da) / out_a;
+        dst[i] = c as u8;
+    }
+    dst[3] = out_a as u8;
+}
+
+/// Expand 8-bit gray / gray+alpha / RGB / RGBA samples to RGBA.
+fn to_rgba(samples: &[u8], channels: usize) -> Vec<u8> {
+    match channels {
+        4 => samples.to_vec(),
+        3 => samples
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        2 => samples
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        _ => samples.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+    }
+}
+
+fn gif_frames(buf: &[u8]) -> Result<Option<Vec<Canvas>>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(buf).map_err(|e| e.to_string())?;
+    let mut canvas = Canvas::new(u32::from(decoder.width()), u32::from(decoder.height()));
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        let (x, y) = (u32::from(frame.left), u32::from(frame.top));
+        let (w, h) = (u32::from(frame.width), u32::from(frame.height));
+        let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.snapshot());
+        // GIF transparency is all-or-nothing, so `Over` just skips the
+        // transparent index.
+        canvas.draw(x, y, w, h, &frame.buffer, Blend::Over);
+        frames.push(canvas.snapshot());
+        match frame.dispose {
+            gif::DisposalMethod::Background => canvas.clear(x, y, w, h),
+            gif::DisposalMethod::Previous => canvas = previous.unwrap_or(canvas),
+            _ => {}
+        }
+    }
+    Ok(Some(frames))
+}
+
+fn apng_frames(buf: &[u8]) -> Result<Option<Vec<Canvas>>, String> {
+    let mut decoder = png::Decoder::new(buf);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let Some(num_frames) = reader.info().animation_control().map(|a| a.num_frames) else {
+        return Ok(None);
+    };
+    let (width, height) = (reader.info().width, reader.info().height);
+    let mut samples = vec![0; reader.output_buffer_size()];
+    // Without an fcTL ahead of IDAT, the default image is a fallback for
+    // non-APNG viewers rather than the first frame.
+    if reader.info().frame_control.is_none() {
+        reader.next_frame(&mut samples).map_err(|e| e.to_string())?;
+    }
+
+    let mut canvas = Canvas::new(width, height);
+    let mut frames = Vec::new();
+    for i in 0..num_frames {
+        let out = reader.next_frame(&mut samples).map_err(|e| e.to_string())?;
+        let fc = reader
+            .info()
+            .frame_control
+            .ok_or_else(|| format!("frame {i} has no fcTL"))?;
+        let len = out.line_size * out.height as usize;
+        let rgba = to_rgba(&samples[..len], out.color_type.samples());
+        // The spec treats PREVIOUS on the first frame as BACKGROUND.
+        let dispose = match fc.dispose_op {
+            png::DisposeOp::Previous if i == 0 => png::DisposeOp::Background,
+            op => op,
+        };
+        let blend = match fc.blend_op {
+            png::BlendOp::Source => Blend::Source,
+            png::BlendOp::Over => Blend::Over,
+        };
+        let previous = (dispose == png::DisposeOp::Previous).then(|| canvas.snapshot());
+        canvas.draw(fc.x_offset, fc.y_offset, out.width, out.height, &rgba, blend);
+        frames.push(canvas.snapshot());
+        match dispose {
+            png::DisposeOp::Background => canvas.clear(fc.x_offset, fc.y_offset, out.width, out.height),
+            png::DisposeOp::Previous => canvas = previous.unwrap_or(canvas),
+            png::DisposeOp::None => {}
+        }
+    }
+    Ok(Some(frames))
+}
+
+fn webp_frames(buf: &[u8]) -> Result<Option<Vec<Canvas>>, String> {
+    let mut decoder =
+        image_webp::WebPDecoder::new(Cursor::new(buf)).map_err(|e| e.to_string())?;
+    if !decoder.is_animated() {
+        return Ok(None);
+    }
+    let (width, height) = decoder.dimensions();
+    let channels = if decoder.has_alpha() { 4 } else { 3 };
+    let size = decoder
+        .output_buffer_size()
+        .ok_or_else(|| format!("{width}x{height} is too large"))?;
+    let mut samples = vec![0; size];
+    // The decoder composites each frame onto its own canvas, so what comes
+    // out is already the displayed frame.
+    (0..decoder.num_frames())
+        .map(|_| {
+            decoder.read_frame(&mut samples).map_err(|e| e.to_string())?;
+            Ok(Canvas {
+                width,
+                height,
+                rgba: to_rgba(&samples, channels),
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two-frame 2×1 GIF: frame 0 is red/red, frame 1 paints only the
+    /// right pixel blue, so the composited frame 1 is red/blue.
+    fn two_frame_gif() -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let palette = [255, 0, 0, 0, 0, 255];
+            let mut encoder = gif::Encoder::new(&mut out, 2, 1, &palette).unwrap();
+            encoder
+                .write_frame(&gif::Frame {
+                    width: 2,
+                    height: 1,
+                    buffer: vec![0, 0].into(),
+                    ..gif::Frame::default()
+                })
+                .unwrap();
+            encoder
+                .write_frame(&gif::Frame {
+                    left: 1,
+                    width: 1,
+                    height: 1,
+                    buffer: vec![1].into(),
+                    ..gif::Frame::default()
+                })
+                .unwrap();
+        }
+        out
+    }
+
+    fn decode_png(buf: &[u8]) -> Vec<u8> {
+        let mut reader = png::Decoder::new(buf).read_info().unwrap();
+        let mut rgba = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut rgba).unwrap();
+        rgba
+    }
+
+    #[test]
+    fn gif_frames_are_composited() {
+        let frames = frames(&two_frame_gif()).unwrap().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(decode_png(&frames[0]), [255, 0, 0, 255, 255, 0, 0, 255]);
+        assert_eq!(decode_png(&frames[1]), [255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn still_images_are_not_split() {
+        let canvas = Canvas::new(1, 1);
+        let png = canvas.encode_png().unwrap();
+        assert!(frames(&png).unwrap().is_none());
+        assert!(frames(b"not an image").unwrap().is_none());
+    }
+
+    #[test]
+    fn over_blends_partial_alpha() {
+        let mut dst = [0, 0, 255, 255];
+        over(&mut dst, &[255, 0, 0, 128]);
+        assert_eq!(dst, [128, 0, 127, 255]);
+    }
+}
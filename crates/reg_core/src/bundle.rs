@@ -0,0 +1,330 @@
+//! Portable copies of a finished report (`Options::bundle`).
+//!
+//! The regular report points at the actual, expected and diff trees where
+//! they are, so sharing it means shipping all three. A bundle holds only
+//! what the report lists:
+//!
+//! ```text
+//! report.html
+//! reg.json
+//! actual/…     actualItems
+//! expected/…   expectedItems
+//! diff/…       diffItems
+//! ```
+//!
+//! Images keep their file paths, which differ from the item names when a
+//! manifest or path mapping paired them (`actualPaths` / `expectedPaths`).
+//! The report and reg.json are rendered again with the directories pointing
+//! at those subdirectories. The destination's extension picks the format:
+//! `.tar` or `.zip` for an archive, streamed to a temporary file beside the
+//! destination and renamed into place, anything else for a plain directory.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    output::PendingOutput,
+    report::{self, create_reports, item_file},
+    write_output, Artifact, CompareError, JsonReport, Options,
+};
+
+const REPORT_NAME: &str = "report.html";
+const JSON_NAME: &str = "reg.json";
+
+/// Write a bundle of `json` to `dest`. `actual_dir`, `expected_dir` and
+/// `diff_dir` are where the listed images are on disk. Listed images that
+/// no longer exist are left out with a warning.
+pub(crate) fn write(
+    dest: &Path,
+    json: &JsonReport,
+    options: &Options,
+    actual_dir: &Path,
+    expected_dir: &Path,
+    diff_dir: &Path,
+) -> Result<(), CompareError> {
+    let mut input = report::ReportInput::from_json_report(
+        json,
+        options,
+        Path::new(REPORT_NAME),
+        Path::new(JSON_NAME),
+    );
+    input.actual_dir = Path::new("actual");
+    input.expected_dir = Path::new("expected");
+    input.diff_dir = Path::new("diff");
+    input.from_json = false;
+    // Everything the bundle needs is inside it.
+    input.url_prefix = None;
+    input.assets_dir = None;
+    input.thumbnails = None;
+    let reports = create_reports(input)?;
+
+    let mut sink = Sink::new(dest)?;
+    let body =
+        serde_json::to_string_pretty(&reports.json).map_err(|source| CompareError::Serialize {
+            artifact: Artifact::Json,
+            source,
+        })?;
+    sink.add(Path::new(JSON_NAME), (body + "\n").as_bytes())?;
+    // Sidecar pages, paths relative to the report.
+    for asset in &reports.assets {
+        sink.add(&asset.path, &asset.contents)?;
+    }
+    if let Some(html) = reports.html {
+        sink.add(Path::new(REPORT_NAME), &html)?;
+    }
+    let no_paths = BTreeMap::new();
+    for (dir, name, items, paths) in [
+        (actual_dir, "actual", &json.actual_items, &json.actual_paths),
+        (expected_dir, "expected", &json.expected_items, &json.expected_paths),
+        (diff_dir, "diff", &json.diff_items, &no_paths),
+    ] {
+        add_images(&mut sink, dir, Path::new(name), items, paths)?;
+    }
+    sink.finish()
+}
+
+fn add_images(
+    sink: &mut Sink,
+    dir: &Path,
+    prefix: &Path,
+    items: &BTreeSet<PathBuf>,
+    paths: &BTreeMap<PathBuf, PathBuf>,
+) -> Result<(), CompareError> {
+    for item in items {
+        let file = item_file(paths, item);
+        if !file.components().all(|c| matches!(c, Component::Normal(_))) {
+            tracing::warn!(path = %file.display(), "skipping bundle entry outside its directory");
+            continue;
+        }
+        let path = dir.join(file);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::warn!(path = %path.display(), "image listed in the report is missing; not bundled");
+                continue;
+            }
+            Err(source) => return Err(CompareError::Read { path, source }),
+        };
+        sink.add(&prefix.join(file), &bytes)?;
+    }
+    Ok(())
+}
+
+enum Sink<'a> {
+    Dir(&'a Path),
+    Tar(&'a Path, tar::Builder<PendingOutput>),
+    Zip(&'a Path, Box<zip::ZipWriter<PendingOutput>>),
+}
+
+impl<'a> Sink<'a> {
+    fn new(dest: &'a Path) -> Result<Self, CompareError> {
+        Ok(match dest.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("tar") => Sink::Tar(
+                dest,
+                tar::Builder::new(PendingOutput::create(Artifact::Bundle, dest)?),
+            ),
+            Some(ext) if ext.eq_ignore_ascii_case("zip") => Sink::Zip(
+                dest,
+                Box::new(zip::ZipWriter::new(PendingOutput::create(
+                    Artifact::Bundle,
+                    dest,
+                )?)),
+            ),
+            _ => Sink::Dir(dest),
+        })
+    }
+
+    /// Add `contents` at the relative path `name`.
+    fn add(&mut self, name: &Path, contents: &[u8]) -> Result<(), CompareError> {
+        // Archive entry names always use `/`, whatever the host separator.
+        let entry = || {
+            name.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        match self {
+            Sink::Dir(dest) => write_output(Artifact::Bundle, dest.join(name), contents),
+            Sink::Tar(dest, builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                // Fixed mtime so identical inputs give identical archives.
+                header.set_mtime(0);
+                builder
+                    .append_data(&mut header, entry(), contents)
+                    .map_err(|source| bundle_error(dest, source))
+            }
+            Sink::Zip(dest, writer) => {
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated);
+                writer
+                    .start_file(entry(), options)
+                    .map_err(io::Error::other)
+                    .and_then(|()| writer.write_all(contents))
+                    .map_err(|source| bundle_error(dest, source))
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), CompareError> {
+        match self {
+            Sink::Dir(_) => Ok(()),
+            // On failure the temporary file is dropped along with the writer.
+            Sink::Tar(dest, builder) => builder
+                .into_inner()
+                .map_err(|source| bundle_error(dest, source))?
+                .commit(),
+            Sink::Zip(dest, writer) => writer
+                .finish()
+                .map_err(|e| bundle_error(dest, io::Error::other(e)))?
+                .commit(),
+        }
+    }
+}
+
+fn bundle_error(dest: &Path, source: io::Error) -> CompareError {
+    CompareError::Write {
+        artifact: Artifact::Bundle,
+        path: dest.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use super::*;
+
+    fn report(tmp: &Path) -> JsonReport {
+        for (dir, file) in [
+            ("actual", "a/x.png"),
+            ("actual", "unused.png"),
+            ("expected", "a/x.png"),
+        ] {
+            let path = tmp.join(dir).join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        serde_json::from_value(serde_json::json!({
+            "failedItems": [],
+            "newItems": [],
+            "deletedItems": [],
+            "passedItems": ["a/x.png"],
+            "expectedItems": ["a/x.png"],
+            "actualItems": ["a/x.png"],
+            "diffItems": ["gone.webp"],
+            "actualDir": "../somewhere/actual",
+            "expectedDir": "../somewhere/expected",
+            "diffDir": "../somewhere/diff",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn directory_bundle_holds_listed_images_and_relative_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let json = report(tmp.path());
+        let dest = tmp.path().join("bundle");
+        write(
+            &dest,
+            &json,
+            &Options::default(),
+            &tmp.path().join("actual"),
+            &tmp.path().join("expected"),
+            &tmp.path().join("diff"),
+        )
+        .unwrap();
+
+        assert!(dest.join("report.html").is_file());
+        assert_eq!(
+            fs::read_to_string(dest.join("actual/a/x.png")).unwrap(),
+            "a/x.png"
+        );
+        assert!(dest.join("expected/a/x.png").is_file());
+        assert!(!dest.join("actual/unused.png").exists());
+        let bundled: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dest.join("reg.json")).unwrap()).unwrap();
+        assert_eq!(bundled["actualDir"], "actual");
+        assert_eq!(bundled["diffDir"], "diff");
+    }
+
+    #[test]
+    fn archives_use_the_same_layout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let json = report(tmp.path());
+        let dirs = ["actual", "expected", "diff"].map(|d| tmp.path().join(d));
+        let [actual, expected, diff] = &dirs;
+        let expected_names = [
+            "reg.json",
+            "report.html",
+            "actual/a/x.png",
+            "expected/a/x.png",
+        ];
+
+        let tar_path = tmp.path().join("out/report.tar");
+        write(
+            &tar_path,
+            &json,
+            &Options::default(),
+            actual,
+            expected,
+            diff,
+        )
+        .unwrap();
+        let mut archive = tar::Archive::new(fs::File::open(&tar_path).unwrap());
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect();
+        assert_eq!(names, expected_names);
+
+        let zip_path = tmp.path().join("out/report.zip");
+        write(
+            &zip_path,
+            &json,
+            &Options::default(),
+            actual,
+            expected,
+            diff,
+        )
+        .unwrap();
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(names.len(), expected_names.len());
+        let mut image = String::new();
+        archive
+            .by_name("actual/a/x.png")
+            .unwrap()
+            .read_to_string(&mut image)
+            .unwrap();
+        assert_eq!(image, "a/x.png");
+    }
+
+    #[test]
+    fn images_paired_from_elsewhere_are_bundled_under_their_file_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut json = report(tmp.path());
+        // A manifest paired the logical name `x.png` with `a/x.png` on disk.
+        json.actual_items = ["x.png".into()].into();
+        json.actual_paths = [("x.png".into(), "a/x.png".into())].into();
+        let dest = tmp.path().join("bundle");
+        write(
+            &dest,
+            &json,
+            &Options::default(),
+            &tmp.path().join("actual"),
+            &tmp.path().join("expected"),
+            &tmp.path().join("diff"),
+        )
+        .unwrap();
+
+        assert!(dest.join("actual/a/x.png").is_file());
+        assert!(!dest.join("actual/x.png").exists());
+    }
+}
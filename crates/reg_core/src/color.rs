@@ -0,0 +1,227 @@
+//! Alpha and colour-profile normalisation ahead of the diff.
+//!
+//! Two screenshots can look identical yet differ pixel-wise: one tool writes
+//! an embedded ICC profile, another tags the same pixels as plain sRGB; one
+//! leaves garbage in the colour channels of fully transparent pixels. These
+//! options decode both sides, fix that up and re-encode as PNG before
+//! `image-diff-rs` sees them. With everything at its default the bytes pass
+//! through untouched, so there's no decode cost unless asked for.
+
+use std::io::Cursor;
+
+use image::ImageReader;
+use moxcms::{ColorProfile, Layout, TransformOptions};
+
+use crate::{report::ReportMetadata, CompareError, Options};
+
+/// How transparency is treated before diffing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// Compare RGBA as decoded.
+    #[default]
+    Keep,
+    /// Composite onto an opaque RGB background first, so only what would be
+    /// visible on that background counts.
+    Flatten([u8; 3]),
+    /// Treat every pixel as opaque; only the colour channels are compared.
+    Ignore,
+}
+
+impl AlphaMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            AlphaMode::Keep => "keep",
+            AlphaMode::Flatten(_) => "flatten",
+            AlphaMode::Ignore => "ignore",
+        }
+    }
+}
+
+/// Parse `#rgb` or `#rrggbb` (the `#` is optional).
+pub fn parse_hex_color(s: &str) -> Result<[u8; 3], CompareError> {
+    let invalid = || CompareError::InvalidOption {
+        option: "color",
+        reason: format!("expected #rgb or #rrggbb, got `{s}`"),
+    };
+    let hex = s.trim().trim_start_matches('#');
+    if !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let digits: Vec<u8> = match hex.len() {
+        3 => hex
+            .chars()
+            .map(|c| u8::from_str_radix(&c.to_string(), 16).map(|v| v * 17))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?,
+        6 => (0..6)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+    Ok([digits[0], digits[1], digits[2]])
+}
+
+pub(crate) fn hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// The settings from `options` that change what "identical" means, for
+/// reg.json. `None` when they're all defaults so classic output is
+/// unchanged.
+pub(crate) fn metadata(options: &Options) -> Option<ReportMetadata> {
+    let alpha = options.alpha_mode.unwrap_or_default();
+    let normalize_srgb = options.normalize_srgb.unwrap_or(false);
+    if alpha == AlphaMode::Keep && !normalize_srgb {
+        return None;
+    }
+    Some(ReportMetadata {
+        alpha: alpha.as_str().to_string(),
+        alpha_background: match alpha {
+            AlphaMode::Flatten(bg) => Some(hex_color(bg)),
+            _ => None,
+        },
+        normalize_srgb,
+    })
+}
+
+/// Apply `alpha` and sRGB normalisation to an encoded image, returning PNG
+/// bytes, or `buf` unchanged when there's nothing to do.
+///
+/// Only embedded ICC profiles are converted; images without one are taken
+/// to be sRGB already.
+pub(crate) fn normalize(
+    buf: Vec<u8>,
+    alpha: AlphaMode,
+    normalize_srgb: bool,
+) -> Result<Vec<u8>, CompareError> {
+    if alpha == AlphaMode::Keep && !normalize_srgb {
+        return Ok(buf);
+    }
+    let decode_error = |reason: String| CompareError::Decode {
+        format: "image",
+        path: None,
+        reason,
+    };
+    let mut decoder = ImageReader::new(Cursor::new(&buf))
+        .with_guessed_format()
+        .map_err(|e| decode_error(e.to_string()))?
+        .into_decoder()
+        .map_err(|e| decode_error(e.to_string()))?;
+    let icc = if normalize_srgb {
+        image::ImageDecoder::icc_profile(&mut decoder).map_err(|e| decode_error(e.to_string()))?
+    } else {
+        None
+    };
+    let mut rgba = image::DynamicImage::from_decoder(decoder)
+        .map_err(|e| decode_error(e.to_string()))?
+        .into_rgba8();
+
+    if let Some(icc) = icc {
+        to_srgb(&icc, &mut rgba).map_err(decode_error)?;
+    }
+    for px in rgba.pixels_mut() {
+        match alpha {
+            AlphaMode::Keep => {}
+            AlphaMode::Flatten(bg) => flatten(&mut px.0, bg),
+            AlphaMode::Ignore => px.0[3] = 255,
+        }
+    }
+
+    let mut png = Cursor::new(Vec::new());
+    rgba.write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| decode_error(e.to_string()))?;
+    Ok(png.into_inner())
+}
+
+fn to_srgb(icc: &[u8], rgba: &mut image::RgbaImage) -> Result<(), String> {
+    let source = ColorProfile::new_from_slice(icc).map_err(|e| format!("ICC profile: {e}"))?;
+    let transform = source
+        .create_transform_8bit(
+            Layout::Rgba,
+            &ColorProfile::new_srgb(),
+            Layout::Rgba,
+            TransformOptions::default(),
+        )
+        .map_err(|e| format!("ICC profile: {e}"))?;
+    let src = rgba.as_raw().clone();
+    transform
+        .transform(&src, rgba.as_mut())
+        .map_err(|e| format!("ICC transform: {e}"))
+}
+
+fn flatten(px: &mut [u8; 4], bg: [u8; 3]) {
+    let a = u32::from(px[3]);
+    for i in 0..3 {
+        px[i] = ((u32::from(px[i]) * a + u32::from(bg[i]) * (255 - a) + 127) / 255) as u8;
+    }
+    px[3] = 255;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(pixels: &[[u8; 4]]) -> Vec<u8> {
+        let img = image::RgbaImage::from_fn(pixels.len() as u32, 1, |x, _| {
+            image::Rgba(pixels[x as usize])
+        });
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, image::ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    fn pixels(png: &[u8]) -> Vec<[u8; 4]> {
+        image::load_from_memory(png)
+            .unwrap()
+            .into_rgba8()
+            .pixels()
+            .map(|p| p.0)
+            .collect()
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_hex_color("#fff").unwrap(), [255, 255, 255]);
+        assert_eq!(parse_hex_color("00ff7f").unwrap(), [0, 255, 127]);
+        assert!(parse_hex_color("#ggg").is_err());
+        assert!(parse_hex_color("#ffff").is_err());
+        assert_eq!(hex_color([0, 255, 127]), "#00ff7f");
+    }
+
+    #[test]
+    fn keep_passes_bytes_through() {
+        let input = b"not even an image".to_vec();
+        assert_eq!(normalize(input.clone(), AlphaMode::Keep, false).unwrap(), input);
+    }
+
+    #[test]
+    fn flatten_and_ignore_alpha() {
+        // Fully transparent pixels with different garbage colours.
+        let a = png(&[[255, 0, 0, 0], [0, 0, 255, 128]]);
+        let b = png(&[[0, 255, 0, 0], [0, 0, 255, 128]]);
+
+        let white = AlphaMode::Flatten([255, 255, 255]);
+        let flat_a = pixels(&normalize(a.clone(), white, false).unwrap());
+        let flat_b = pixels(&normalize(b, white, false).unwrap());
+        assert_eq!(flat_a, flat_b);
+        assert_eq!(flat_a, [[255, 255, 255, 255], [127, 127, 255, 255]]);
+
+        let opaque = pixels(&normalize(a, AlphaMode::Ignore, false).unwrap());
+        assert_eq!(opaque, [[255, 0, 0, 255], [0, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn metadata_only_when_non_default() {
+        assert!(metadata(&Options::default()).is_none());
+        let meta = metadata(&Options {
+            alpha_mode: Some(AlphaMode::Flatten([0, 0, 0])),
+            ..Options::default()
+        })
+        .unwrap();
+        assert_eq!(meta.alpha, "flatten");
+        assert_eq!(meta.alpha_background.as_deref(), Some("#000000"));
+        assert!(!meta.normalize_srgb);
+    }
+}
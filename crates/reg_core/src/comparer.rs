@@ -0,0 +1,1380 @@
+//! Long-lived comparison entry point.
+//!
+//! `run` used to build a fresh rayon pool on every call, which adds up for
+//! library users that compare once per Storybook story group. A `Comparer`
+//! holds the options plus a pool taken from the process-wide cache in
+//! `schedule`, so repeated comparisons land on warm workers. `run` is now a
+//! one-shot wrapper around `Comparer::compare_dirs`.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image_diff_rs::{DiffOption, DiffOutput, EncodeFormat};
+use once_cell::sync::OnceCell;
+use rayon::{prelude::*, ThreadPool};
+use serde::Serialize;
+use tracing::{info, info_span};
+
+use crate::{
+    animation, bundle, color, diff_style,
+    embed::EmbedImages,
+    emit_progress, find_images, format, is_passed,
+    manifest::Manifest,
+    mapping,
+    output::{self, OutputLock},
+    prune,
+    report::{self, create_reports, item_file, FrameReport, RenamedItem},
+    schedule,
+    thumbnail::Thumbnails,
+    write_output, Artifact, CompareError, DetectedImages, JsonReport, Options, WalkOptions, DEFAULT_JSON_PATH,
+    DEFAULT_REPORT_PATH,
+};
+
+/// One actual/expected pair to diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImagePair {
+    /// Logical name used in reg.json, progress events and the diff image
+    /// path under `diff_dir`.
+    pub name: PathBuf,
+    pub actual: PathBuf,
+    pub expected: PathBuf,
+}
+
+/// Outcome of diffing a single pair.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairResult {
+    /// Whether the pair is within `threshold_pixel` / `threshold_rate`.
+    pub passed: bool,
+    pub diff_count: u64,
+    /// `diff_count / (width * height)`, the quantity `threshold_rate` is
+    /// checked against. `0.0` for zero-area images.
+    pub diff_ratio: f32,
+    pub width: u32,
+    pub height: u32,
+    /// Encoded diff image (see `Options::diff_image_format`). `None` when
+    /// the images are pixel-identical. Not serialized; write it out
+    /// yourself if you need it on disk.
+    #[serde(skip)]
+    pub diff_image: Option<Vec<u8>>,
+    /// Set when both inputs were compared frame by frame
+    /// (`Options::compare_frames` with at least one animated side).
+    /// `diff_count` and `diff_ratio` then cover every compared frame and
+    /// `diff_image` is the first of `frame_diff_images`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames: Option<FrameReport>,
+    /// `(frame index, encoded diff image)` for every failed frame, or for a
+    /// passed pair every frame that differed within the thresholds. Not
+    /// serialized.
+    #[serde(skip)]
+    pub frame_diff_images: Vec<(usize, Vec<u8>)>,
+}
+
+/// Diff two image files without staging actual/expected directories.
+///
+/// Only the comparison options (`matching_threshold`, `threshold_*`,
+/// `enable_antialias`, `diff_image_format`, `diff_style`) are consulted; nothing is
+/// written to disk.
+pub fn compare_files(
+    actual: impl AsRef<Path>,
+    expected: impl AsRef<Path>,
+    options: Options,
+) -> Result<PairResult, CompareError> {
+    Comparer::new(options).compare_pair(actual, expected)
+}
+
+/// Like [`compare_files`], for images already in memory.
+pub fn compare_buffers(
+    actual: impl Into<Vec<u8>>,
+    expected: impl Into<Vec<u8>>,
+    options: Options,
+) -> Result<PairResult, CompareError> {
+    Comparer::new(options).compare_bytes(actual, expected)
+}
+
+/// One entry of [`Comparer::compare_pairs`]: the pair's name and its
+/// result, or the error that kept it from being diffed.
+pub type PairOutcome = (PathBuf, Result<PairResult, CompareError>);
+
+/// Reusable comparison context. Cheap to keep around; create one per set of
+/// options and call its `compare_*` methods as often as needed.
+///
+/// ```no_run
+/// use reg_core::{Comparer, Options};
+///
+/// let comparer = Comparer::new(Options::default());
+/// for group in ["button", "modal"] {
+///     let actual = format!("actual/{group}");
+///     let expected = format!("expected/{group}");
+///     let diff = format!("diff/{group}");
+///     comparer.compare_dirs(&actual, &expected, &diff).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Comparer<'a> {
+    options: Options<'a>,
+    /// Pool pinned on first use when `options.concurrency` is explicit.
+    /// Auto-sized comparers pick a shared pool per batch instead, since the
+    /// right size depends on the images in that batch.
+    pool: OnceCell<Arc<ThreadPool>>,
+}
+
+impl<'a> Comparer<'a> {
+    pub fn new(options: Options<'a>) -> Self {
+        Self {
+            options,
+            pool: OnceCell::new(),
+        }
+    }
+
+    pub fn options(&self) -> &Options<'a> {
+        &self.options
+    }
+
+    /// Diff two image files. Unlike the batch methods, read and decode
+    /// errors are returned to the caller rather than folded into a result.
+    pub fn compare_pair(
+        &self,
+        actual: impl AsRef<Path>,
+        expected: impl AsRef<Path>,
+    ) -> Result<PairResult, CompareError> {
+        let actual = actual.as_ref();
+        self.compare_bytes(read_image(actual)?, read_image(expected.as_ref())?)
+            .map_err(|e| e.for_image(actual))
+    }
+
+    /// Diff two encoded images held in memory.
+    pub fn compare_bytes(
+        &self,
+        actual: impl Into<Vec<u8>>,
+        expected: impl Into<Vec<u8>>,
+    ) -> Result<PairResult, CompareError> {
+        self.diff_bytes(actual.into(), expected.into())
+    }
+
+    /// Diff an explicit list of pairs in parallel, returning one entry per
+    /// input in the same order.
+    ///
+    /// Per-pair errors are never propagated out of the rayon closure —
+    /// instead each failure is logged as a tracing error, fired as a `compare-event`
+    /// of kind "fail", and handed back as `Err` for the caller to fold into
+    /// the `failedItems` bucket. This matches classic reg-cli's tolerance
+    /// (it forks per image, so a single corrupt PNG can't sink the whole
+    /// batch).
+    pub fn compare_pairs(
+        &self,
+        pairs: &[ImagePair],
+    ) -> Result<Vec<PairOutcome>, CompareError> {
+        let plan = {
+            let plan_span = info_span!(
+                "plan_concurrency",
+                target_count = pairs.len(),
+                threads = tracing::field::Empty,
+                source = tracing::field::Empty,
+                total_area = tracing::field::Empty,
+            );
+            let _plan_guard = plan_span.enter();
+            let plan = schedule::plan_concurrency(
+                self.options.concurrency,
+                pairs.iter().map(|p| p.actual.as_path()),
+            );
+            plan_span.record("threads", plan.threads);
+            plan_span.record("source", plan.source.as_str());
+            plan_span.record("total_area", plan.total_area);
+            plan
+        };
+        info!(
+            target_count = pairs.len(),
+            concurrency = plan.threads,
+            source = plan.source.as_str(),
+            total_area = plan.total_area,
+            "Starting parallel image diff"
+        );
+
+        let pool = {
+            let _pool_span = info_span!("acquire_thread_pool", num_threads = plan.threads).entered();
+            self.pool(plan.threads)?
+        };
+
+        let diff_span = info_span!("parallel_image_diff", target_count = pairs.len());
+        let _diff_guard = diff_span.enter();
+
+        // Capture the parent span to propagate to rayon threads
+        let parent_span = diff_span.clone();
+
+        Ok(pool.install(|| {
+            // Note: There may be ~20-30ms delay here due to rayon thread scheduling overhead
+            // This is especially noticeable in WASI environments
+            pairs
+                .par_iter()
+                .map(|pair| {
+                    // Explicitly set parent span for cross-thread context propagation
+                    let image_span = info_span!(parent: parent_span.clone(), "diff_single_image", image = %pair.name.display());
+                    let _image_guard = image_span.enter();
+                    (pair.name.clone(), self.diff_batch_item(pair))
+                })
+                .collect()
+        }))
+    }
+
+    /// The pool to diff on: the pinned one for an explicit `concurrency`,
+    /// otherwise the shared pool with `threads` workers.
+    fn pool(&self, threads: usize) -> Result<Arc<ThreadPool>, CompareError> {
+        match self.options.concurrency {
+            Some(threads) => Ok(self
+                .pool
+                .get_or_try_init(|| schedule::shared_pool(threads))?
+                .clone()),
+            None => schedule::shared_pool(threads),
+        }
+    }
+
+    /// Walk `actual_dir` and `expected_dir`, diff every image present on
+    /// both sides, write diff images plus reg.json / HTML / JUnit, and
+    /// return the report. This is what `run` does.
+    ///
+    /// With `options.manifest` set, pairs come from the manifest instead of
+    /// the walk, and both directories act as bases for its relative paths.
+    /// Otherwise `options.path_mappings` decides which expected path each
+    /// actual image pairs with.
+    pub fn compare_dirs(
+        &self,
+        actual_dir: impl AsRef<Path>,
+        expected_dir: impl AsRef<Path>,
+        diff_dir: impl AsRef<Path>,
+    ) -> Result<JsonReport, CompareError> {
+        let options = &self.options;
+        let actual_dir = actual_dir.as_ref();
+        let expected_dir = expected_dir.as_ref();
+        let diff_dir = diff_dir.as_ref();
+        let json_path = options.json.unwrap_or_else(|| Path::new(DEFAULT_JSON_PATH));
+        let report = options
+            .report
+            .unwrap_or_else(|| Path::new(DEFAULT_REPORT_PATH));
+
+        info!(
+            actual_dir = %actual_dir.display(),
+            expected_dir = %expected_dir.display(),
+            diff_dir = %diff_dir.display(),
+            "Starting image comparison"
+        );
+
+        // Held until every artefact is written, and taken before anything is
+        // announced: a second run writing any of the same outputs fails here
+        // instead of interleaving writes.
+        let pages_dir = options.report.map(report::pages_dir);
+        let _lock = OutputLock::acquire(
+            [
+                Some(diff_dir),
+                Some(json_path),
+                options.report,
+                pages_dir.as_deref(),
+                options.report_assets_dir,
+                options.thumbnail_dir,
+                options.junit_report,
+                options.bundle,
+            ]
+            .into_iter()
+            .flatten(),
+        )?;
+
+        let manifest = options
+            .manifest
+            .map(|path| Manifest::load(path).map(|manifest| (path, manifest)))
+            .transpose()?;
+        let (mut detected, pairs) = match &manifest {
+            Some((path, manifest)) => manifest.detect(path, actual_dir, expected_dir)?,
+            None => mapping::pair_walked(
+                find_images(
+                    expected_dir,
+                    actual_dir,
+                    WalkOptions::from_options(options),
+                    options.allow_empty_expected.unwrap_or(false),
+                )?,
+                options.path_mappings.unwrap_or_default(),
+                options.case_insensitive_paths.unwrap_or(false),
+                actual_dir,
+                expected_dir,
+            )?,
+        };
+
+        // Has to run before the `new` / `delete` events below so a rename
+        // isn't announced as both.
+        let renamed = if options.detect_renames.unwrap_or(false) {
+            let _rename_span = info_span!(
+                "detect_renames",
+                new_count = detected.new.len(),
+                deleted_count = detected.deleted.len()
+            )
+            .entered();
+            self.detect_renames(&mut detected, actual_dir, expected_dir)?
+        } else {
+            BTreeSet::new()
+        };
+
+        // Emit `new` / `delete` progress events up front — classic reg-cli
+        // fires these before the per-image diff loop starts, and reg-suit /
+        // spinners depend on live progress. See `emit_progress` for the wire
+        // format and `js/entry.ts` for the receiving side.
+        for p in &detected.new {
+            emit_progress("new", &p.display().to_string());
+        }
+        for p in &detected.deleted {
+            emit_progress("delete", &p.display().to_string());
+        }
+        for r in &renamed {
+            emit_progress("pass", &r.to.display().to_string());
+        }
+
+        let clean = options.clean_diff_dir;
+        let stale = if clean.is_some() {
+            prune::load(diff_dir)
+        } else {
+            BTreeSet::new()
+        };
+        if clean == Some(prune::CleanDiffDir::Before) {
+            prune::remove(diff_dir, &stale);
+        }
+
+        let result = self.compare_pairs(&pairs)?;
+
+        let mut passed = BTreeSet::new();
+        let mut failed = BTreeSet::new();
+        let mut tolerated = BTreeSet::new();
+        let mut frames = BTreeMap::new();
+        let write_tolerated = options.write_tolerated_diffs.unwrap_or(false);
+        let diff_extension = options.diff_image_format.unwrap_or_default().extension();
+        // Every diff image written, failed or tolerated.
+        let mut written = BTreeSet::new();
+        // The diffs of failed pairs, listed as `diffItems`.
+        let mut differences = BTreeSet::new();
+        // Writes `<name>.<ext>`, or for animations `<name>.frame<iii>.<ext>`
+        // per differing frame, whose first is also `diff_image`. Returns the
+        // names written.
+        let mut write_diffs = |name: &Path,
+                               diff_image: Option<Vec<u8>>,
+                               frame_images: &[(usize, Vec<u8>)]|
+         -> Result<Vec<PathBuf>, CompareError> {
+            let mut images = Vec::new();
+            for (i, image) in frame_images {
+                let mut frame_name = name.to_path_buf();
+                frame_name.set_extension(format!("frame{i:03}.{diff_extension}"));
+                images.push((frame_name, image.as_slice()));
+            }
+            if let Some(image) = diff_image.as_ref().filter(|_| frame_images.is_empty()) {
+                images.push((name.with_extension(diff_extension), image.as_slice()));
+            }
+            for (diff_name, image) in &images {
+                write_output(Artifact::DiffImage, diff_dir.join(diff_name), image)?;
+            }
+            let names: Vec<_> = images.into_iter().map(|(diff_name, _)| diff_name).collect();
+            written.extend(names.iter().cloned());
+            Ok(names)
+        };
+
+        for (image_name, item) in result {
+            match item {
+                Err(_) => {
+                    // Per-file read/decode failure: count as failed but
+                    // don't try to write a diff image (we have no pixels).
+                    failed.insert(image_name);
+                }
+                Ok(PairResult {
+                    passed: true,
+                    diff_image,
+                    frames: frame_report,
+                    frame_diff_images,
+                    ..
+                }) => {
+                    if let Some(frame_report) = frame_report {
+                        frames.insert(image_name.clone(), frame_report);
+                    }
+                    // Diff images are only set for a pass when some pixels
+                    // differed within the thresholds.
+                    if write_tolerated
+                        && !write_diffs(&image_name, diff_image, &frame_diff_images)?.is_empty()
+                    {
+                        tolerated.insert(image_name.clone());
+                    }
+                    passed.insert(image_name);
+                }
+                Ok(PairResult {
+                    passed: false,
+                    diff_image,
+                    frames: frame_report,
+                    frame_diff_images,
+                    ..
+                }) => {
+                    if let Some(frame_report) = frame_report {
+                        frames.insert(image_name.clone(), frame_report);
+                    }
+                    // An animation can fail on frame count alone, with no
+                    // differing pixels to draw.
+                    differences.extend(write_diffs(&image_name, diff_image, &frame_diff_images)?);
+                    failed.insert(image_name);
+                }
+            }
+        }
+
+        output::sync_outputs(Artifact::DiffImage, diff_dir, &written)?;
+
+        if clean == Some(prune::CleanDiffDir::After) {
+            prune::remove(diff_dir, stale.difference(&written));
+        }
+        // Only runs that clean keep the list, so without the option the diff
+        // dir holds nothing but diff images.
+        if clean.is_some() {
+            prune::save(diff_dir, &written)?;
+        }
+
+        let mut thumbnails = Thumbnails::from_options(options);
+        if let Some(thumbnails) = &mut thumbnails {
+            let _thumbnail_span = info_span!("write_thumbnails").entered();
+            let files = |dir: &Path, items: &BTreeSet<PathBuf>, paths| -> Vec<_> {
+                items
+                    .iter()
+                    .map(|name| (name.clone(), dir.join(item_file(paths, name))))
+                    .collect()
+            };
+            let actual = files(actual_dir, &detected.actual, &detected.actual_paths);
+            let expected = files(expected_dir, &detected.expected, &detected.expected_paths);
+            let diff: Vec<_> = written
+                .iter()
+                .map(|name| (name.clone(), diff_dir.join(name)))
+                .collect();
+            // On the comparer's pool, like the diffs, not rayon's global one.
+            let pool = self.pool(schedule::available_parallelism())?;
+            pool.install(|| {
+                thumbnails.write("actual", &actual)?;
+                thumbnails.write("expected", &expected)?;
+                thumbnails.write("diff", &diff)
+            })?;
+        }
+
+        let report = {
+            let _report_span = info_span!("create_reports").entered();
+            info!(
+                passed_count = passed.len(),
+                failed_count = failed.len(),
+                new_count = detected.new.len(),
+                deleted_count = detected.deleted.len(),
+                "Creating reports"
+            );
+            create_reports(report::ReportInput {
+                passed,
+                failed,
+                new: detected.new,
+                deleted: detected.deleted,
+                actual: detected.actual,
+                expected: detected.expected,
+                report,
+                template: options.template,
+                assets_dir: options.report_assets_dir,
+                embed: options.embed_images.unwrap_or(false).then_some(EmbedImages {
+                    actual_dir,
+                    expected_dir,
+                    diff_dir,
+                    thumbnail_size: options.embed_thumbnail_size,
+                }),
+                passed_limit: options.report_passed_limit,
+                page_size: options.report_page_size,
+                thumbnails,
+                differences,
+                renamed,
+                tolerated,
+                unsupported: detected.unsupported,
+                format_mismatches: detected.format_mismatches,
+                frames,
+                actual_paths: detected.actual_paths,
+                expected_paths: detected.expected_paths,
+                metadata: color::metadata(options),
+                json: json_path,
+                actual_dir,
+                expected_dir,
+                diff_dir,
+                from_json: false,
+                url_prefix: options.url_prefix.clone(),
+                diff_image_extention: diff_extension,
+                enable_client_additional_detection: options
+                    .enable_client_additional_detection
+                    .unwrap_or(false),
+            })?
+        };
+
+        if let (Some(html), Some(report_path)) = (report.html, options.report) {
+            let _write_span = info_span!("write_report", path = %report_path.display()).entered();
+            // Assets first, so the report never links to a missing file.
+            for asset in &report.assets {
+                write_output(Artifact::ReportAsset, &asset.path, &asset.contents)?;
+            }
+            write_output(Artifact::Report, report_path, html)?;
+            info!(path = %report_path.display(), "Report written");
+        };
+
+        // Persist reg.json to disk. Previously this was done on the JS side after
+        // the Wasm returned the string; moving it to Rust makes the contract
+        // symmetric with HTML / diff images (reg_core writes every artefact it
+        // knows how to produce) and keeps the non-wasm `cargo run` CLI useful.
+        {
+            let _write_span = info_span!("write_json", path = %json_path.display()).entered();
+            let body = serde_json::to_string_pretty(&report.json).map_err(|source| {
+                CompareError::Serialize {
+                    artifact: Artifact::Json,
+                    source,
+                }
+            })?;
+            write_output(Artifact::Json, json_path, body + "\n")?;
+        }
+
+        // JUnit XML (optional).
+        if let Some(junit_path) = options.junit_report {
+            let _write_span = info_span!("write_junit", path = %junit_path.display()).entered();
+            let xml = report::build_junit_xml(
+                &report.json,
+                options.extended_errors.unwrap_or(false),
+            );
+            write_output(Artifact::Junit, junit_path, xml)?;
+        }
+
+        if let Some(dest) = options.bundle {
+            let _write_span = info_span!("write_bundle", path = %dest.display()).entered();
+            bundle::write(dest, &report.json, options, actual_dir, expected_dir, diff_dir)?;
+        }
+
+        info!("Comparison complete");
+        Ok(report.json)
+    }
+
+    /// Pair deleted and new images by content. Byte-identical files are
+    /// matched first, looked up by length and hash; what's left is diffed
+    /// pairwise on the diff pool, only for pairs whose headers give the same
+    /// dimensions, and paired when the diff passes the configured
+    /// thresholds, which by default means pixel-identical but differently
+    /// encoded. Each new image takes the first deleted image, in name order,
+    /// that it matches. Matches are removed from `detected.new` /
+    /// `detected.deleted`.
+    ///
+    /// Only fingerprints are kept between passes; the workers read the
+    /// files they diff themselves.
+    fn detect_renames(
+        &self,
+        detected: &mut DetectedImages,
+        actual_dir: &Path,
+        expected_dir: &Path,
+    ) -> Result<BTreeSet<RenamedItem>, CompareError> {
+        let actual_file = |name| actual_dir.join(item_file(&detected.actual_paths, name));
+        let expected_file = |name| expected_dir.join(item_file(&detected.expected_paths, name));
+
+        let deleted: Vec<(&PathBuf, PathBuf, Fingerprint)> = detected
+            .deleted
+            .iter()
+            .filter_map(|name| {
+                let file = expected_file(name);
+                let bytes = std::fs::read(&file).ok()?;
+                Some((name, file, Fingerprint::of(&bytes)))
+            })
+            .collect();
+        let mut by_content: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+        let mut by_dims: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (i, (_, _, print)) in deleted.iter().enumerate() {
+            by_content.entry((print.len, print.hash)).or_default().push(i);
+            if let Some(dims) = print.dims {
+                by_dims.entry(dims).or_default().push(i);
+            }
+        }
+        let mut taken = vec![false; deleted.len()];
+        let mut renamed = BTreeSet::new();
+
+        let mut unmatched = Vec::new();
+        for name in &detected.new {
+            let file = actual_file(name);
+            let Ok(bytes) = std::fs::read(&file) else {
+                continue;
+            };
+            let print = Fingerprint::of(&bytes);
+            let exact = by_content
+                .get(&(print.len, print.hash))
+                .into_iter()
+                .flatten()
+                .copied()
+                .find(|&i| {
+                    // Rule out hash collisions.
+                    !taken[i] && std::fs::read(&deleted[i].1).is_ok_and(|b| b == bytes)
+                });
+            match exact {
+                Some(i) => {
+                    taken[i] = true;
+                    renamed.insert(RenamedItem {
+                        from: deleted[i].0.clone(),
+                        to: name.clone(),
+                    });
+                }
+                None => {
+                    if let Some(dims) = print.dims {
+                        unmatched.push((name, file, dims));
+                    }
+                }
+            }
+        }
+
+        let candidates: Vec<(usize, usize)> = unmatched
+            .iter()
+            .enumerate()
+            .flat_map(|(n, (_, _, dims))| {
+                by_dims
+                    .get(dims)
+                    .into_iter()
+                    .flatten()
+                    .filter(|&&d| !taken[d])
+                    .map(move |&d| (n, d))
+            })
+            .collect();
+        if !candidates.is_empty() {
+            let pool = self.pool(schedule::available_parallelism())?;
+            let passed: Vec<bool> = pool.install(|| {
+                candidates
+                    .par_iter()
+                    .map(|&(n, d)| {
+                        read_image(&unmatched[n].1)
+                            .and_then(|a| Ok((a, read_image(&deleted[d].1)?)))
+                            .and_then(|(a, e)| self.diff_bytes(a, e))
+                            .is_ok_and(|r| r.passed)
+                    })
+                    .collect()
+            });
+            // Candidates are grouped by new image, deleted images in name
+            // order, so the first free hit per new image is the match.
+            let mut matched = vec![false; unmatched.len()];
+            for (&(n, d), passed) in candidates.iter().zip(passed) {
+                if passed && !taken[d] && !matched[n] {
+                    taken[d] = true;
+                    matched[n] = true;
+                    renamed.insert(RenamedItem {
+                        from: deleted[d].0.clone(),
+                        to: unmatched[n].0.clone(),
+                    });
+                }
+            }
+        }
+
+        for r in &renamed {
+            detected.new.remove(&r.to);
+            detected.deleted.remove(&r.from);
+        }
+        info!(renamed_count = renamed.len(), "Detected renames");
+        Ok(renamed)
+    }
+
+    /// Batch wrapper around `diff_bytes`: logs failures with the offending
+    /// path and fires the live pass/fail event as soon as this pair is done,
+    /// so consumers see progress while other rayon threads are still working.
+    fn diff_batch_item(&self, pair: &ImagePair) -> Result<PairResult, CompareError> {
+        let name = pair.name.to_string_lossy();
+        let res = read_image(&pair.actual)
+            .and_then(|actual| Ok((actual, read_image(&pair.expected)?)))
+            .and_then(|(actual, expected)| self.diff_bytes(actual, expected))
+            .map_err(|e| {
+                let e = e.for_image(&pair.name);
+                tracing::error!(image = %name, code = e.code(), error = %e, "image comparison failed");
+                emit_progress("fail", &name);
+                e
+            })?;
+        emit_progress(if res.passed { "pass" } else { "fail" }, &name);
+        Ok(res)
+    }
+
+    fn diff_bytes(
+        &self,
+        actual: Vec<u8>,
+        expected: Vec<u8>,
+    ) -> Result<PairResult, CompareError> {
+        let svg_scale = self.options.svg_scale.unwrap_or(1.0);
+        let actual = format::prepare(actual, svg_scale)?;
+        let expected = format::prepare(expected, svg_scale)?;
+        if self.options.compare_frames.unwrap_or(false) {
+            let actual_frames = animation::frames(&actual)?;
+            let expected_frames = animation::frames(&expected)?;
+            if actual_frames.is_some() || expected_frames.is_some() {
+                // A still image on the other side counts as one frame.
+                return self.diff_frames(
+                    actual_frames.unwrap_or_else(|| vec![actual]),
+                    expected_frames.unwrap_or_else(|| vec![expected]),
+                );
+            }
+        }
+        self.diff_encoded(actual, expected)
+    }
+
+    /// Diff two animations frame by frame and fold the per-frame results.
+    /// Frames beyond the shorter side aren't compared; a count mismatch
+    /// fails the pair on its own.
+    fn diff_frames(
+        &self,
+        actual: Vec<Vec<u8>>,
+        expected: Vec<Vec<u8>>,
+    ) -> Result<PairResult, CompareError> {
+        let mut report = FrameReport {
+            actual_frames: actual.len(),
+            expected_frames: expected.len(),
+            failed_frames: Vec::new(),
+        };
+        let mut failed_images = Vec::new();
+        let mut tolerated_images = Vec::new();
+        let (mut width, mut height) = (0, 0);
+        let (mut diff_count, mut area) = (0u64, 0u64);
+        for (i, (a, e)) in actual.into_iter().zip(expected).enumerate() {
+            let res = self.diff_encoded(a, e)?;
+            if i == 0 {
+                (width, height) = (res.width, res.height);
+            }
+            diff_count += res.diff_count;
+            area += u64::from(res.width) * u64::from(res.height);
+            if res.passed {
+                tolerated_images.extend(res.diff_image.map(|img| (i, img)));
+            } else {
+                report.failed_frames.push(i);
+                failed_images.extend(res.diff_image.map(|img| (i, img)));
+            }
+        }
+        let passed = report.failed_frames.is_empty() && !report.frame_count_mismatch();
+        // A failed pair shows what failed; a passed one what it tolerated.
+        let frame_diff_images = if passed {
+            tolerated_images
+        } else {
+            failed_images
+        };
+        Ok(PairResult {
+            passed,
+            diff_count,
+            diff_ratio: if area == 0 {
+                0.0
+            } else {
+                diff_count as f32 / area as f32
+            },
+            width,
+            height,
+            diff_image: frame_diff_images.first().map(|(_, img)| img.clone()),
+            frames: Some(report),
+            frame_diff_images,
+        })
+    }
+
+    fn diff_encoded(
+        &self,
+        actual: Vec<u8>,
+        expected: Vec<u8>,
+    ) -> Result<PairResult, CompareError> {
+        let options = &self.options;
+        let alpha = options.alpha_mode.unwrap_or_default();
+        let normalize_srgb = options.normalize_srgb.unwrap_or(false);
+        let actual = color::normalize(actual, alpha, normalize_srgb)?;
+        let expected = color::normalize(expected, alpha, normalize_srgb)?;
+        // `DiffOutput::Eq` carries no dimensions; read them from the header
+        // before the buffer is moved into the decoder.
+        let header_dims = schedule::image_dimensions(&actual);
+        // The styled renderer needs both inputs after the engine has
+        // consumed them.
+        let styled = options
+            .diff_style
+            .map(|style| (style, actual.clone(), expected.clone()));
+        let res = image_diff_rs::diff(
+            actual,
+            expected,
+            &DiffOption {
+                threshold: options.matching_threshold,
+                include_anti_alias: Some(!options.enable_antialias.unwrap_or_default()),
+                encode_format: options.diff_image_format.map(EncodeFormat::from),
+            },
+        )?;
+        Ok(match res {
+            DiffOutput::Eq => {
+                let (width, height) = header_dims.unwrap_or_default();
+                PairResult {
+                    passed: true,
+                    diff_count: 0,
+                    diff_ratio: 0.0,
+                    width,
+                    height,
+                    diff_image: None,
+                    frames: None,
+                    frame_diff_images: Vec::new(),
+                }
+            }
+            DiffOutput::NotEq {
+                diff_count,
+                diff_image,
+                width,
+                height,
+            } => {
+                let diff_count = diff_count as u64;
+                let diff_image = match styled {
+                    Some((style, actual, expected)) => diff_style::render(
+                        &actual,
+                        &expected,
+                        &style,
+                        options.matching_threshold.unwrap_or(0.0),
+                        options.diff_image_format.unwrap_or_default(),
+                    )?,
+                    None => diff_image,
+                };
+                PairResult {
+                    passed: is_passed(
+                        width,
+                        height,
+                        diff_count,
+                        options.threshold_pixel,
+                        options.threshold_rate,
+                    ),
+                    diff_count,
+                    diff_ratio: diff_ratio(width, height, diff_count),
+                    width,
+                    height,
+                    diff_image: Some(diff_image),
+                    frames: None,
+                    frame_diff_images: Vec::new(),
+                }
+            }
+        })
+    }
+}
+
+/// What rename detection keeps of a file between passes.
+struct Fingerprint {
+    len: usize,
+    hash: u64,
+    /// From the header; `None` when the format isn't recognised.
+    dims: Option<(u32, u32)>,
+}
+
+impl Fingerprint {
+    fn of(bytes: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        Fingerprint {
+            len: bytes.len(),
+            hash: hasher.finish(),
+            dims: schedule::image_dimensions(bytes),
+        }
+    }
+}
+
+fn read_image(path: &Path) -> Result<Vec<u8>, CompareError> {
+    std::fs::read(path).map_err(|source| CompareError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn diff_ratio(width: u32, height: u32, diff_count: u64) -> f32 {
+    let area = u64::from(width) * u64::from(height);
+    if area == 0 {
+        0.0
+    } else {
+        diff_count as f32 / area as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::per_image_failure_tests::TINY_PNG;
+    use std::fs;
+
+    /// A temp dir with empty `actual/` and `expected/` directories, and the
+    /// paths a run writes its diffs, reg.json and report to.
+    struct Fixture {
+        root: tempfile::TempDir,
+        actual: PathBuf,
+        expected: PathBuf,
+        diff: PathBuf,
+        json: PathBuf,
+        report: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = tempfile::tempdir().unwrap();
+            let actual = root.path().join("actual");
+            let expected = root.path().join("expected");
+            fs::create_dir_all(&actual).unwrap();
+            fs::create_dir_all(&expected).unwrap();
+            Self {
+                diff: root.path().join("diff"),
+                json: root.path().join("reg.json"),
+                report: root.path().join("report.html"),
+                actual,
+                expected,
+                root,
+            }
+        }
+
+        fn path(&self, relative: &str) -> PathBuf {
+            self.root.path().join(relative)
+        }
+
+        /// Options writing reg.json and the report into the fixture.
+        fn options(&self) -> Options<'_> {
+            Options {
+                json: Some(&self.json),
+                report: Some(&self.report),
+                ..Options::default()
+            }
+        }
+
+        fn run(&self, options: Options) -> Result<JsonReport, CompareError> {
+            Comparer::new(options).compare_dirs(&self.actual, &self.expected, &self.diff)
+        }
+
+        fn html(&self) -> String {
+            fs::read_to_string(&self.report).unwrap()
+        }
+    }
+
+    /// One comparer, several directory pairs: the pinned pool must be the
+    /// same one every time and each call writes its own reg.json.
+    #[test]
+    fn comparer_is_reusable_across_dir_pairs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let json = tmp.path().join("reg.json");
+        let comparer = Comparer::new(Options {
+            json: Some(json.as_path()),
+            concurrency: Some(2),
+            ..Options::default()
+        });
+        let mut pinned = None;
+        for group in ["a", "b"] {
+            let actual = tmp.path().join(group).join("actual");
+            let expected = tmp.path().join(group).join("expected");
+            fs::create_dir_all(&actual).unwrap();
+            fs::create_dir_all(&expected).unwrap();
+            fs::write(actual.join(format!("{group}.png")), TINY_PNG).unwrap();
+            fs::write(expected.join(format!("{group}.png")), TINY_PNG).unwrap();
+
+            let report = comparer
+                .compare_dirs(&actual, &expected, tmp.path().join(group).join("diff"))
+                .unwrap();
+            assert_eq!(report.passed_items.len(), 1);
+            let pool = comparer.pool.get().unwrap();
+            assert!(Arc::ptr_eq(pinned.get_or_insert_with(|| pool.clone()), pool));
+        }
+    }
+
+    #[test]
+    fn compare_pairs_keeps_input_order_and_isolates_errors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let good = tmp.path().join("good.png");
+        fs::write(&good, TINY_PNG).unwrap();
+
+        let pairs = vec![
+            ImagePair {
+                name: "missing.png".into(),
+                actual: tmp.path().join("missing.png"),
+                expected: good.clone(),
+            },
+            ImagePair {
+                name: "good.png".into(),
+                actual: good.clone(),
+                expected: good.clone(),
+            },
+        ];
+        let out = Comparer::new(Options::default()).compare_pairs(&pairs).unwrap();
+        assert_eq!(out[0].0, Path::new("missing.png"));
+        assert!(out[0].1.is_err());
+        assert_eq!(out[1].0, Path::new("good.png"));
+        assert!(out[1].1.as_ref().unwrap().passed);
+    }
+
+    #[test]
+    fn compare_buffers_identical_images_pass_with_header_dims() {
+        let res = compare_buffers(TINY_PNG, TINY_PNG, Options::default()).unwrap();
+        assert!(res.passed);
+        assert_eq!(res.diff_count, 0);
+        assert_eq!(res.diff_ratio, 0.0);
+        assert_eq!((res.width, res.height), (1, 1));
+        assert!(res.diff_image.is_none());
+    }
+
+    #[test]
+    fn compare_files_reports_decode_errors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.png");
+        let b = tmp.path().join("b.png");
+        fs::write(&a, b"not a png AAA").unwrap();
+        fs::write(&b, b"not a png BBB").unwrap();
+        assert!(matches!(
+            compare_files(&a, &b, Options::default()),
+            Err(CompareError::Diff { .. })
+        ));
+    }
+
+    #[test]
+    fn diff_ratio_handles_zero_area() {
+        assert_eq!(diff_ratio(0, 10, 5), 0.0);
+        assert_eq!(diff_ratio(10, 10, 5), 0.05);
+    }
+
+    #[test]
+    fn compare_dirs_with_manifest_reports_logical_names() {
+        let fx = Fixture::new();
+        fs::write(fx.actual.join("home.png"), TINY_PNG).unwrap();
+        fs::write(fx.expected.join("home@chrome.png"), TINY_PNG).unwrap();
+        let manifest = fx.path("pairs.csv");
+        fs::write(&manifest, "name,actual,expected\nhome,home.png,home@chrome.png\n").unwrap();
+
+        let report = fx
+            .run(Options {
+                manifest: Some(manifest.as_path()),
+                ..fx.options()
+            })
+            .unwrap();
+        assert!(report.passed_items.contains(Path::new("home")));
+        assert!(report.new_items.is_empty());
+        assert!(report.deleted_items.is_empty());
+        assert_eq!(report.actual_paths[Path::new("home")], Path::new("home.png"));
+        assert_eq!(
+            report.expected_paths[Path::new("home")],
+            Path::new("home@chrome.png")
+        );
+        // Previews of `home` load the real files.
+        let html = fx.html();
+        assert!(html.contains(r#""actual/home":{"src":"actual/home.png"}"#));
+        assert!(html.contains(r#""expected/home":{"src":"expected/home%40chrome.png"}"#));
+    }
+
+    #[test]
+    fn renamed_image_is_reported_once_as_renamed() {
+        let fx = Fixture::new();
+        fs::write(fx.actual.join("story--new-name.png"), TINY_PNG).unwrap();
+        fs::write(fx.expected.join("story--old-name.png"), TINY_PNG).unwrap();
+
+        let report = fx
+            .run(Options {
+                detect_renames: Some(true),
+                ..fx.options()
+            })
+            .unwrap();
+        assert!(report.new_items.is_empty());
+        assert!(report.deleted_items.is_empty());
+        assert_eq!(
+            report.renamed_items.into_iter().collect::<Vec<_>>(),
+            vec![RenamedItem {
+                from: "story--old-name.png".into(),
+                to: "story--new-name.png".into(),
+            }]
+        );
+        let html = fx.html();
+        assert!(html.contains(r#""hasNew":false"#));
+        assert!(html.contains(r#""hasDeleted":false"#));
+        assert!(html.contains(
+            r#"story--old-name.png &rarr; <a href="actual/story--new-name.png">story--new-name.png</a>"#
+        ));
+    }
+
+    #[test]
+    fn path_mappings_pair_differently_named_baselines() {
+        let fx = Fixture::new();
+        fs::write(fx.actual.join("home.png"), TINY_PNG).unwrap();
+        fs::write(fx.expected.join("home@chrome.png"), TINY_PNG).unwrap();
+        let rules = [mapping::PathMapping::new(r"^(.*)\.png$", "$1@chrome.png").unwrap()];
+
+        let report = fx
+            .run(Options {
+                path_mappings: Some(&rules),
+                ..fx.options()
+            })
+            .unwrap();
+        assert!(report.passed_items.contains(Path::new("home.png")));
+        assert!(report.deleted_items.is_empty());
+        assert_eq!(
+            report.expected_paths[Path::new("home.png")],
+            Path::new("home@chrome.png")
+        );
+    }
+
+    /// 1×1 GIF cycling through `colors` (palette indices into red/blue).
+    fn animated_gif(colors: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut out, 1, 1, &[255, 0, 0, 0, 0, 255]).unwrap();
+            for &c in colors {
+                encoder
+                    .write_frame(&gif::Frame {
+                        width: 1,
+                        height: 1,
+                        buffer: vec![c].into(),
+                        ..gif::Frame::default()
+                    })
+                    .unwrap();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn compare_frames_reports_frame_count_mismatch() {
+        let options = || Options {
+            compare_frames: Some(true),
+            ..Options::default()
+        };
+        let same = compare_buffers(animated_gif(&[0, 1]), animated_gif(&[0, 1]), options()).unwrap();
+        assert!(same.passed);
+        assert_eq!(
+            same.frames,
+            Some(FrameReport {
+                actual_frames: 2,
+                expected_frames: 2,
+                failed_frames: vec![],
+            })
+        );
+
+        let longer =
+            compare_buffers(animated_gif(&[0, 1]), animated_gif(&[0, 1, 0]), options()).unwrap();
+        assert!(!longer.passed);
+        assert!(longer.frames.as_ref().unwrap().frame_count_mismatch());
+        assert!(longer.diff_image.is_none());
+    }
+
+    #[test]
+    fn frame_diffs_are_written_once_and_the_first_shown_as_the_failure() {
+        let fx = Fixture::new();
+        fs::write(fx.actual.join("anim.gif"), animated_gif(&[0, 1, 1])).unwrap();
+        fs::write(fx.expected.join("anim.gif"), animated_gif(&[0, 0, 0])).unwrap();
+
+        let report = fx
+            .run(Options {
+                compare_frames: Some(true),
+                ..fx.options()
+            })
+            .unwrap();
+        assert_eq!(
+            report.diff_items.into_iter().collect::<Vec<_>>(),
+            [
+                PathBuf::from("anim.frame001.webp"),
+                PathBuf::from("anim.frame002.webp")
+            ]
+        );
+        assert!(!fx.diff.join("anim.webp").exists());
+        let html = fx.html();
+        assert!(html.contains(r#""failedItems":[{"raw":"anim.frame001.webp""#));
+        assert!(!html.contains(r#"{"raw":"anim.frame002.webp""#));
+    }
+
+    #[test]
+    fn clean_run_removes_diffs_only_it_recorded() {
+        let fx = Fixture::new();
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255]))
+            .save(fx.actual.join("a.png"))
+            .unwrap();
+        fs::write(fx.expected.join("a.png"), TINY_PNG).unwrap();
+        let clean = || Options {
+            clean_diff_dir: Some(prune::CleanDiffDir::After),
+            ..fx.options()
+        };
+
+        fx.run(fx.options()).unwrap();
+        assert!(!fx.diff.join(prune::MANIFEST_NAME).exists());
+        fx.run(clean()).unwrap();
+        assert!(fx.diff.join(prune::MANIFEST_NAME).is_file());
+        fs::write(fx.actual.join("a.png"), TINY_PNG).unwrap();
+        fx.run(clean()).unwrap();
+        assert!(!fx.diff.join("a.webp").exists());
+    }
+
+    #[test]
+    fn tolerated_diffs_are_written_when_asked() {
+        let fx = Fixture::new();
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255]))
+            .save(fx.actual.join("a.png"))
+            .unwrap();
+        fs::write(fx.expected.join("a.png"), TINY_PNG).unwrap();
+
+        let report = fx
+            .run(Options {
+                threshold_pixel: Some(1),
+                write_tolerated_diffs: Some(true),
+                ..fx.options()
+            })
+            .unwrap();
+        assert_eq!(report.passed_items.len(), 1);
+        assert!(report.diff_items.is_empty());
+        assert_eq!(
+            report.tolerated_items.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("a.png")]
+        );
+        assert!(fx.diff.join("a.webp").is_file());
+        // The UI shows it as a plain pass; the template lists the diff.
+        assert!(fx.html().contains(r#"<img src="diff/a.webp" alt="a.png""#));
+    }
+
+    #[test]
+    fn custom_template_gets_items_and_metrics() {
+        let fx = Fixture::new();
+        fs::write(fx.actual.join("a.png"), TINY_PNG).unwrap();
+        fs::write(fx.expected.join("a.png"), TINY_PNG).unwrap();
+        fs::write(fx.actual.join("b.png"), TINY_PNG).unwrap();
+        let template = fx.path("brand.html");
+        fs::write(
+            &template,
+            "{{metrics.total}}/{{metrics.passed}} {{#newItems}}[{{raw}}]{{/newItems}}",
+        )
+        .unwrap();
+        let options = || Options {
+            template: Some(template.as_path()),
+            ..fx.options()
+        };
+
+        fx.run(options()).unwrap();
+        assert_eq!(fx.html(), "2/1 [b.png]");
+
+        fs::write(&template, "{{#newItems}}").unwrap();
+        let err = fx.run(options()).unwrap_err();
+        assert_eq!(err.path(), Some(template.as_path()));
+    }
+
+    #[test]
+    fn report_assets_are_written_and_linked() {
+        let fx = Fixture::new();
+        let report = fx.path("out/report.html");
+        let assets = fx.path("out/assets");
+        let render = |assets_dir| {
+            fx.run(Options {
+                report: Some(report.as_path()),
+                report_assets_dir: assets_dir,
+                allow_empty_expected: Some(true),
+                ..fx.options()
+            })
+            .unwrap();
+            fs::read_to_string(&report).unwrap()
+        };
+
+        let inline = render(None);
+        assert!(inline.contains("\n    <style>"));
+        assert!(inline.contains("data:image/png;base64,"));
+
+        let linked = render(Some(assets.as_path()));
+        let mut names: Vec<_> = fs::read_dir(&assets)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 3);
+        for name in &names {
+            assert!(linked.contains(&format!("\"assets/{name}\"")), "{name} not linked");
+        }
+        assert!(!linked.contains("<style>"));
+    }
+
+    #[test]
+    fn embedded_images_are_keyed_by_the_ui_url() {
+        let fx = Fixture::new();
+        fs::write(fx.actual.join("new.png"), b"not decoded").unwrap();
+        let report = fx.path("out/report.html");
+        fx.run(Options {
+            report: Some(report.as_path()),
+            embed_images: Some(true),
+            embed_thumbnail_size: Some(10),
+            allow_empty_expected: Some(true),
+            ..fx.options()
+        })
+        .unwrap();
+
+        let html = fs::read_to_string(&report).unwrap();
+        // Undecodable images are embedded as-is rather than thumbnailed.
+        assert!(html.contains(r#"window['__reg_images__'] = {"../actual/new.png":{"src":"data:image/png;base64,bm90IGRlY29kZWQ="}};"#));
+        assert!(html.contains("new MutationObserver"));
+    }
+
+    #[test]
+    fn long_passed_lists_are_paged_out_of_the_report() {
+        let fx = Fixture::new();
+        for name in ["p1", "p2", "p3", "p4", "p5"] {
+            fs::write(fx.actual.join(format!("{name}.png")), TINY_PNG).unwrap();
+            fs::write(fx.expected.join(format!("{name}.png")), TINY_PNG).unwrap();
+        }
+        for name in ["n1", "n2", "n3"] {
+            fs::write(fx.actual.join(format!("{name}.png")), name).unwrap();
+        }
+        let result = fx
+            .run(Options {
+                report_page_size: Some(2),
+                ..fx.options()
+            })
+            .unwrap();
+        assert_eq!(result.passed_items.len(), 5);
+
+        let html = fx.html();
+        // New items stay inline however many there are.
+        assert!(html.contains(r#""newItems":[{"raw":"n1.png","encoded":"n1.png"},{"raw":"n2.png","encoded":"n2.png"},{"raw":"n3.png","encoded":"n3.png"}]"#));
+        assert!(html.contains(r#""totals":{"total":8,"passed":5,"failed":0,"new":3,"#));
+        assert!(html.contains(
+            r#""pages":{"passedItems":["report.pages/passedItems-1.json","report.pages/passedItems-2.json"]}"#
+        ));
+        assert!(html.contains("__reg_pages__"));
+        assert!(html.contains("Not every item is shown here: 2 of 5 passed; reg.json lists them all."));
+        assert_eq!(
+            fs::read_to_string(fx.path("report.pages/passedItems-2.json")).unwrap(),
+            r#"[{"raw":"p5.png","encoded":"p5.png"}]"#
+        );
+
+        fx.run(Options {
+            report_passed_limit: Some(0),
+            ..fx.options()
+        })
+        .unwrap();
+        let html = fx.html();
+        assert!(html.contains(r#""hasPassed":true,"passedItems":[]"#));
+        assert!(html.contains("Not every item is shown here: 0 of 5 passed;"));
+    }
+
+    #[test]
+    fn run_fails_while_another_holds_its_thumbnail_dir() {
+        let fx = Fixture::new();
+        let thumbs = fx.path("thumbs");
+        let _held = OutputLock::acquire([thumbs.as_path()]).unwrap();
+
+        let err = fx
+            .run(Options {
+                thumbnail_dir: Some(&thumbs),
+                ..fx.options()
+            })
+            .unwrap_err();
+        assert!(matches!(err, CompareError::OutputLocked { .. }));
+    }
+
+    #[test]
+    fn thumbnails_are_written_and_served_by_the_report() {
+        let fx = Fixture::new();
+        image::RgbaImage::new(2, 2).save(fx.actual.join("a.png")).unwrap();
+        fs::copy(fx.actual.join("a.png"), fx.expected.join("a.png")).unwrap();
+        let thumbs = fx.path("thumbs");
+        fx.run(Options {
+            thumbnail_dir: Some(&thumbs),
+            ..fx.options()
+        })
+        .unwrap();
+
+        assert!(thumbs.join("actual/a.png.webp").is_file());
+        assert!(thumbs.join("expected/a.png.webp").is_file());
+        let html = fx.html();
+        assert!(html.contains(
+            r#""thumbnails":{"actualDir":"thumbs/actual","expectedDir":"thumbs/expected","diffDir":"thumbs/diff","extension":"webp"}"#
+        ));
+        // Served in place of the originals, which load when drawn larger.
+        assert!(html.contains(
+            r#""actual/a.png":{"src":"thumbs/actual/a.png.webp","full":"actual/a.png"}"#
+        ));
+        assert!(html.contains("new MutationObserver"));
+    }
+
+    #[test]
+    fn compare_pair_propagates_read_errors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let res = Comparer::new(Options::default())
+            .compare_pair(tmp.path().join("a.png"), tmp.path().join("b.png"));
+        assert!(matches!(res, Err(CompareError::Read { .. })));
+    }
+}
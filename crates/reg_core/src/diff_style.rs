@@ -0,0 +1,187 @@
+//! Restyled diff images.
+//!
+//! `image-diff-rs` draws its diff image with fixed colours. When
+//! `Options::diff_style` is set we render our own instead: changed pixels in
+//! the highlight colour over a dimmed greyscale copy of the expected image,
+//! optionally as an `expected | actual | diff` strip that reads on its own
+//! when attached to a ticket.
+//!
+//! Changed pixels are found with the same YIQ colour distance and
+//! `matching_threshold` the diff engine counts with. Its anti-aliasing
+//! detection isn't re-run, so with `enable_antialias` unset a few AA pixels
+//! the count ignored can still show up highlighted.
+
+use std::io::Cursor;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{CompareError, DiffImageFormat};
+
+/// How to draw diff images. The default matches the engine's look (red on
+/// a faded copy) so only the fields you care about need setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffStyle {
+    /// Colour for changed pixels.
+    pub highlight: [u8; 3],
+    /// How far unchanged pixels fade toward white, from `0.0` (greyscale
+    /// as is) to `1.0` (blank).
+    pub dim: f32,
+    /// Emit `expected | actual | diff` side by side instead of the diff
+    /// panel alone.
+    pub side_by_side: bool,
+}
+
+impl Default for DiffStyle {
+    fn default() -> Self {
+        Self {
+            highlight: [255, 0, 0],
+            dim: 0.9,
+            side_by_side: false,
+        }
+    }
+}
+
+/// Same constant pixelmatch uses: the largest possible YIQ delta.
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+/// Render a styled diff image for two encoded inputs. Images of different
+/// sizes are compared on the larger canvas; pixels only one side has count
+/// as changed.
+pub(crate) fn render(
+    actual: &[u8],
+    expected: &[u8],
+    style: &DiffStyle,
+    matching_threshold: f32,
+    format: DiffImageFormat,
+) -> Result<Vec<u8>, CompareError> {
+    let decode = |buf: &[u8]| {
+        image::load_from_memory(buf)
+            .map(DynamicImage::into_rgba8)
+            .map_err(|e| CompareError::Decode {
+                format: "image",
+                path: None,
+                reason: e.to_string(),
+            })
+    };
+    let actual = decode(actual)?;
+    let expected = decode(expected)?;
+    let width = actual.width().max(expected.width());
+    let height = actual.height().max(expected.height());
+    let max_delta = MAX_YIQ_DELTA * matching_threshold * matching_threshold;
+
+    let [r, g, b] = style.highlight;
+    let highlight = Rgba([r, g, b, 255]);
+    let diff = RgbaImage::from_fn(width, height, |x, y| {
+        match (
+            actual.get_pixel_checked(x, y),
+            expected.get_pixel_checked(x, y),
+        ) {
+            (Some(a), Some(e)) if yiq_delta(a.0, e.0) <= max_delta => {
+                let v = faded_luma(e.0, style.dim);
+                Rgba([v, v, v, 255])
+            }
+            _ => highlight,
+        }
+    });
+
+    let out = if style.side_by_side {
+        let mut strip = RgbaImage::from_pixel(width * 3, height, Rgba([255, 255, 255, 255]));
+        image::imageops::overlay(&mut strip, &expected, 0, 0);
+        image::imageops::overlay(&mut strip, &actual, i64::from(width), 0);
+        image::imageops::overlay(&mut strip, &diff, i64::from(width) * 2, 0);
+        strip
+    } else {
+        diff
+    };
+    let format = match format {
+        DiffImageFormat::Webp => image::ImageFormat::WebP,
+        DiffImageFormat::Png => image::ImageFormat::Png,
+    };
+    let mut buf = Cursor::new(Vec::new());
+    out.write_to(&mut buf, format)
+        .map_err(|e| CompareError::Decode {
+            format: "image",
+            path: None,
+            reason: e.to_string(),
+        })?;
+    Ok(buf.into_inner())
+}
+
+/// Alpha-blend onto white, then YIQ-weighted squared distance (pixelmatch's
+/// `colorDelta`).
+fn yiq_delta(a: [u8; 4], b: [u8; 4]) -> f32 {
+    let yiq = |p: [u8; 4]| {
+        let alpha = f32::from(p[3]) / 255.0;
+        let [r, g, b] = [p[0], p[1], p[2]].map(|c| 255.0 + (f32::from(c) - 255.0) * alpha);
+        (
+            r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_23,
+            r * 0.595_977_99 - g * 0.274_176_1 - b * 0.321_801_9,
+            r * 0.211_470_17 - g * 0.522_617_1 + b * 0.311_146_94,
+        )
+    };
+    let (y1, i1, q1) = yiq(a);
+    let (y2, i2, q2) = yiq(b);
+    let (dy, di, dq) = (y1 - y2, i1 - i2, q1 - q2);
+    0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq
+}
+
+fn faded_luma(p: [u8; 4], dim: f32) -> u8 {
+    let luma = 0.299 * f32::from(p[0]) + 0.587 * f32::from(p[1]) + 0.114 * f32::from(p[2]);
+    let alpha = f32::from(p[3]) / 255.0;
+    // Transparent pixels fade to white like the rest of the background.
+    let luma = 255.0 + (luma - 255.0) * alpha;
+    let dim = dim.clamp(0.0, 1.0);
+    (luma * (1.0 - dim) + 255.0 * dim).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(pixels: &[[u8; 4]]) -> Vec<u8> {
+        let img = RgbaImage::from_fn(pixels.len() as u32, 1, |x, _| Rgba(pixels[x as usize]));
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, image::ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    fn decode(buf: &[u8]) -> RgbaImage {
+        image::load_from_memory(buf).unwrap().into_rgba8()
+    }
+
+    #[test]
+    fn highlights_changes_and_dims_the_rest() {
+        let expected = png(&[[0, 0, 0, 255], [0, 0, 0, 255]]);
+        let actual = png(&[[0, 0, 0, 255], [255, 255, 255, 255]]);
+        let style = DiffStyle {
+            highlight: [0, 255, 0],
+            dim: 0.5,
+            side_by_side: false,
+        };
+        let out = decode(&render(&actual, &expected, &style, 0.0, DiffImageFormat::Png).unwrap());
+        assert_eq!(out.get_pixel(0, 0).0, [128, 128, 128, 255]);
+        assert_eq!(out.get_pixel(1, 0).0, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn side_by_side_is_three_panels_wide() {
+        let a = png(&[[10, 20, 30, 255]]);
+        let b = png(&[[10, 20, 30, 255], [0, 0, 0, 255]]);
+        let style = DiffStyle {
+            side_by_side: true,
+            ..DiffStyle::default()
+        };
+        let out = decode(&render(&a, &b, &style, 0.1, DiffImageFormat::Png).unwrap());
+        assert_eq!(out.dimensions(), (6, 1));
+        assert_eq!(out.get_pixel(2, 0).0, [10, 20, 30, 255]);
+        // Only the expected side has the second pixel.
+        assert_eq!(out.get_pixel(5, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn threshold_tolerates_small_deltas() {
+        let delta = yiq_delta([100, 100, 100, 255], [101, 100, 100, 255]);
+        assert!(delta > 0.0);
+        assert!(delta <= MAX_YIQ_DELTA * 0.1 * 0.1);
+    }
+}
@@ -0,0 +1,283 @@
+//! Images served to the HTML report from other URLs than the UI builds.
+//!
+//! The report UI loads images from `actualDir` / `expectedDir` / `diffDir`
+//! plus each item's encoded path. `template/embed.js` swaps `<img>` sources
+//! matching an entry of `window['__reg_images__']` for the entry's `src`,
+//! which covers two cases:
+//!
+//! - Items stored under another relative path than their name (a pair
+//!   manifest, or `path_mappings` on the expected side) are pointed at the
+//!   real file.
+//! - With embedding (`Options::embed_images`), every image the report
+//!   lists is read and stored as a data URL, so the HTML works on its own.
+//!   With a thumbnail size, rasters larger than it are downscaled to PNG
+//!   first and the original stays reachable by its relative URL, loaded
+//!   only when the image is drawn larger than the thumbnail.
+//! - Otherwise, with `Options::thumbnail_dir`, images are served from their
+//!   written thumbnails the same way (`Thumbnails::images`).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
+use serde::Serialize;
+
+use crate::{
+    report::{encode_file_path, item_file},
+    thumbnail, DiffImageFormat, ImageFormat,
+};
+
+/// Loader script added to the report when any image is served this way.
+pub(crate) const LOADER: &str = include_str!("../../../template/embed.js");
+
+/// Where the report's images live on disk and how to embed them.
+pub(crate) struct EmbedImages<'a> {
+    pub(crate) actual_dir: &'a Path,
+    pub(crate) expected_dir: &'a Path,
+    pub(crate) diff_dir: &'a Path,
+    /// Longest side of embedded thumbnails; `None` embeds originals.
+    pub(crate) thumbnail_size: Option<u32>,
+}
+
+/// One of the report's image directories as the UI sees it.
+pub(crate) struct ReportImages<'a> {
+    /// Directory URL the UI prefixes item paths with.
+    pub(crate) href: &'a Path,
+    /// Items the UI loads from there.
+    pub(crate) items: &'a BTreeSet<PathBuf>,
+    /// Items stored under another relative path, item name → file.
+    pub(crate) paths: &'a BTreeMap<PathBuf, PathBuf>,
+}
+
+impl ReportImages<'_> {
+    /// The URL the UI requests for `name`.
+    pub(crate) fn url(&self, name: &Path) -> String {
+        format!("{}/{}", self.href.display(), encode_file_path(name))
+    }
+
+    /// The URL of the file actually holding `name`.
+    pub(crate) fn file_url(&self, name: &Path) -> String {
+        self.url(item_file(self.paths, name))
+    }
+
+    /// Every name the UI may request, with the file it should get.
+    fn files(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.items
+            .iter()
+            .chain(self.paths.keys().filter(|name| !self.items.contains(*name)))
+            .map(|name| (name.as_path(), item_file(self.paths, name)))
+    }
+}
+
+/// Entries pointing the UI at the real files of items stored under another
+/// relative path.
+pub(crate) fn redirects(dirs: [&ReportImages; 3]) -> BTreeMap<String, EmbeddedImage> {
+    dirs.iter()
+        .flat_map(|dir| {
+            dir.paths.iter().map(|(name, file)| {
+                let image = EmbeddedImage {
+                    src: dir.url(file),
+                    full: None,
+                };
+                (dir.url(name), image)
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct EmbeddedImage {
+    src: String,
+    /// For thumbnails, the original's URL relative to the report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full: Option<String>,
+}
+
+impl EmbeddedImage {
+    /// A thumbnail at `src` standing in for the image at `full`.
+    pub(crate) fn thumbnail(src: String, full: String) -> Self {
+        Self {
+            src,
+            full: Some(full),
+        }
+    }
+}
+
+impl EmbedImages<'_> {
+    /// Embed the report's images, keyed by the URL the UI requests.
+    pub(crate) fn collect(
+        &self,
+        actual: &ReportImages,
+        expected: &ReportImages,
+        diff: &ReportImages,
+    ) -> BTreeMap<String, EmbeddedImage> {
+        let mut images = BTreeMap::new();
+        for (dir, images_dir) in [
+            (self.actual_dir, actual),
+            (self.expected_dir, expected),
+            (self.diff_dir, diff),
+        ] {
+            for (name, file) in images_dir.files() {
+                // The full-size original, should this become a thumbnail.
+                if let Some(image) = self.embed(&dir.join(file), images_dir.url(file)) {
+                    images.insert(images_dir.url(name), image);
+                }
+            }
+        }
+        images
+    }
+
+    /// Files that can't be read are skipped with a warning; the UI then
+    /// falls back to the relative URL.
+    fn embed(&self, path: &Path, url: String) -> Option<EmbeddedImage> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "cannot embed image");
+                return None;
+            }
+        };
+        // Only rasters larger than the thumbnail size are downscaled; the
+        // rest, and anything that can't be decoded here, embed as they are.
+        let png = self
+            .thumbnail_size
+            .and_then(|max| thumbnail::resize(&bytes, max))
+            .filter(|&(_, scaled)| scaled)
+            .and_then(|(img, _)| thumbnail::encode(&img, DiffImageFormat::Png));
+        if let Some(png) = png {
+            return Some(EmbeddedImage::thumbnail(data_url("image/png", &png), url));
+        }
+        let mime = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(ImageFormat::from_extension)
+            .map_or("application/octet-stream", ImageFormat::mime_type);
+        Some(EmbeddedImage {
+            src: data_url(mime, &bytes),
+            full: None,
+        })
+    }
+}
+
+/// Serialise embedded images for a `<script>` block.
+pub(crate) fn to_script_json(
+    images: &BTreeMap<String, EmbeddedImage>,
+) -> Result<String, serde_json::Error> {
+    // `</` would end the script element early; `<\/` parses the same.
+    Ok(serde_json::to_string(images)?.replace("</", "<\\/"))
+}
+
+fn data_url(mime: &str, bytes: &[u8]) -> String {
+    format!("data:{mime};base64,{}", BASE64_STANDARD.encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        image::RgbaImage::new(width, height)
+            .write_to(&mut out, image::ImageFormat::Png)
+            .unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn large_images_become_thumbnails_with_a_full_size_url() {
+        let tmp = tempfile::tempdir().unwrap();
+        let actual = tmp.path().join("actual");
+        std::fs::create_dir_all(actual.join("a b")).unwrap();
+        std::fs::write(actual.join("a b/big.png"), png(400, 100)).unwrap();
+        std::fs::write(actual.join("small.png"), png(10, 10)).unwrap();
+        let items: BTreeSet<PathBuf> = ["a b/big.png", "small.png", "missing.png"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+
+        let embed = EmbedImages {
+            actual_dir: &actual,
+            expected_dir: tmp.path(),
+            diff_dir: tmp.path(),
+            thumbnail_size: Some(100),
+        };
+        let none = BTreeSet::new();
+        let no_paths = BTreeMap::new();
+        let dir = |href: &'static str, items| ReportImages {
+            href: Path::new(href),
+            items,
+            paths: &no_paths,
+        };
+        let images = embed.collect(
+            &dir("../actual", &items),
+            &dir("../expected", &none),
+            &dir("../diff", &none),
+        );
+
+        assert_eq!(images.len(), 2);
+        let key = format!("../actual/a%20b{}big.png", std::path::MAIN_SEPARATOR);
+        let big = &images[&key];
+        assert_eq!(big.full.as_deref(), Some(key.as_str()));
+        let thumb = BASE64_STANDARD
+            .decode(big.src.strip_prefix("data:image/png;base64,").unwrap())
+            .unwrap();
+        let thumb = image::load_from_memory(&thumb).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (100, 25));
+
+        let small = &images["../actual/small.png"];
+        assert!(small.full.is_none());
+        assert_eq!(small.src, data_url("image/png", &png(10, 10)));
+    }
+
+    #[test]
+    fn items_stored_elsewhere_are_served_from_their_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("home@chrome.png"), png(4, 4)).unwrap();
+        let items: BTreeSet<PathBuf> = [PathBuf::from("home@chrome.png")].into();
+        let paths: BTreeMap<PathBuf, PathBuf> =
+            [(PathBuf::from("home.png"), PathBuf::from("home@chrome.png"))].into();
+        let expected = ReportImages {
+            href: Path::new("expected"),
+            items: &items,
+            paths: &paths,
+        };
+        let none = BTreeSet::new();
+        let no_paths = BTreeMap::new();
+        let other = ReportImages {
+            href: Path::new("other"),
+            items: &none,
+            paths: &no_paths,
+        };
+
+        let redirected = redirects([&other, &expected, &other]);
+        assert_eq!(redirected.len(), 1);
+        assert_eq!(redirected["expected/home.png"].src, "expected/home%40chrome.png");
+
+        let embed = EmbedImages {
+            actual_dir: tmp.path(),
+            expected_dir: tmp.path(),
+            diff_dir: tmp.path(),
+            thumbnail_size: None,
+        };
+        let embedded = embed.collect(&other, &expected, &other);
+        assert_eq!(embedded.len(), 2);
+        assert_eq!(embedded["expected/home.png"].src, data_url("image/png", &png(4, 4)));
+    }
+
+    #[test]
+    fn script_json_cannot_close_the_script_element() {
+        let mut images = BTreeMap::new();
+        images.insert(
+            "</script>".to_string(),
+            EmbeddedImage {
+                src: String::new(),
+                full: None,
+            },
+        );
+        assert!(!to_script_json(&images).unwrap().contains("</"));
+    }
+}
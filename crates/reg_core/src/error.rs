@@ -0,0 +1,256 @@
+//! The crate's error type.
+//!
+//! Each [`CompareError`] variant maps to a [`Stage`] of the pipeline and a
+//! stable [`code`](CompareError::code), so callers (the CLI, the Wasm host,
+//! CI tooling reading its output) can branch on what failed without parsing
+//! messages. Codes are part of the public contract: add new ones, never
+//! rename or reuse them.
+
+use std::path::{Path, PathBuf};
+
+use image_diff_rs::ImageDiffError;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::Artifact;
+
+/// Where in a run an error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Validating options, path mappings or a pair manifest.
+    Config,
+    /// Walking and pairing the image directories.
+    Discovery,
+    /// Reading input files.
+    Read,
+    /// Decoding or transcoding an image.
+    Decode,
+    /// Running the pixel diff.
+    Diff,
+    /// Rendering the HTML report.
+    Report,
+    /// Serialising reg.json.
+    Serialize,
+    /// Writing an output file.
+    Write,
+}
+
+#[derive(Error, Debug)]
+pub enum CompareError {
+    #[error("cannot read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("image diff error{}, {source}", for_path(path))]
+    Diff {
+        /// Logical image name, when the failing pair is known.
+        path: Option<PathBuf>,
+        source: ImageDiffError,
+    },
+    #[error("invalid manifest {}: {reason}", path.display())]
+    Manifest { path: PathBuf, reason: String },
+    #[error("cannot read {role} directory {}: {source}", path.display())]
+    Directory {
+        role: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid path mapping `{rule}`: {reason}")]
+    InvalidPathMapping { rule: String, reason: String },
+    #[error(
+        "{} and {} collide when paths are matched case-insensitively",
+        first.display(),
+        second.display()
+    )]
+    PathCollision { first: PathBuf, second: PathBuf },
+    #[error("unsupported image format `{format}`: {reason}")]
+    UnsupportedFormat { format: String, reason: String },
+    #[error("cannot decode {format} image{}: {reason}", for_path(path))]
+    Decode {
+        format: &'static str,
+        /// Logical image name, when the failing pair is known.
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    #[error("invalid {option}: {reason}")]
+    InvalidOption { option: &'static str, reason: String },
+    #[error("invalid reg.json {}: {source}", path.display())]
+    InvalidReportJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("cannot serialize {artifact}: {source}")]
+    Serialize {
+        artifact: Artifact,
+        source: serde_json::Error,
+    },
+    #[error("invalid report template{}: {reason}", for_path(path))]
+    Template {
+        /// `None` for the built-in template.
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    #[error("cannot render {artifact}: {reason}")]
+    Render { artifact: Artifact, reason: String },
+    #[error("cannot write {artifact} to {}: {source}", path.display())]
+    Write {
+        artifact: Artifact,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "{} is held by another reg-cli run writing the same outputs ({holder})",
+        path.display()
+    )]
+    OutputLocked { path: PathBuf, holder: String },
+    #[error("cannot start {threads} diff worker threads: {reason}")]
+    ThreadPool { threads: usize, reason: String },
+}
+
+fn for_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|p| format!(" for {}", p.display()))
+        .unwrap_or_default()
+}
+
+impl CompareError {
+    /// Stable machine-readable identifier, e.g. `DECODE_FAILED`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompareError::Read { .. } => "READ_FAILED",
+            CompareError::Diff { .. } => "DIFF_FAILED",
+            CompareError::Manifest { .. } => "INVALID_MANIFEST",
+            CompareError::Directory { .. } => "DISCOVERY_FAILED",
+            CompareError::InvalidPathMapping { .. } => "INVALID_PATH_MAPPING",
+            CompareError::PathCollision { .. } => "PATH_COLLISION",
+            CompareError::UnsupportedFormat { .. } => "UNSUPPORTED_FORMAT",
+            CompareError::Decode { .. } => "DECODE_FAILED",
+            CompareError::InvalidOption { .. } => "INVALID_OPTION",
+            CompareError::InvalidReportJson { .. } => "INVALID_REG_JSON",
+            CompareError::Serialize { .. } => "SERIALIZE_FAILED",
+            CompareError::Template { .. } => "INVALID_TEMPLATE",
+            CompareError::Render { .. } => "RENDER_FAILED",
+            CompareError::Write { .. } => "WRITE_FAILED",
+            CompareError::OutputLocked { .. } => "OUTPUT_LOCKED",
+            CompareError::ThreadPool { .. } => "THREAD_POOL_FAILED",
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        match self {
+            CompareError::Read { .. } | CompareError::InvalidReportJson { .. } => Stage::Read,
+            CompareError::Diff { .. } | CompareError::ThreadPool { .. } => Stage::Diff,
+            CompareError::Manifest { .. }
+            | CompareError::InvalidPathMapping { .. }
+            | CompareError::UnsupportedFormat { .. }
+            | CompareError::InvalidOption { .. } => Stage::Config,
+            CompareError::Directory { .. } | CompareError::PathCollision { .. } => {
+                Stage::Discovery
+            }
+            CompareError::Decode { .. } => Stage::Decode,
+            CompareError::Serialize { .. } => Stage::Serialize,
+            CompareError::Template { .. } | CompareError::Render { .. } => Stage::Report,
+            CompareError::Write { .. } | CompareError::OutputLocked { .. } => Stage::Write,
+        }
+    }
+
+    /// The file or directory the error is about, if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            CompareError::Read { path, .. }
+            | CompareError::Manifest { path, .. }
+            | CompareError::Directory { path, .. }
+            | CompareError::InvalidReportJson { path, .. }
+            | CompareError::Write { path, .. }
+            | CompareError::OutputLocked { path, .. } => Some(path),
+            CompareError::PathCollision { first, .. } => Some(first),
+            CompareError::Diff { path, .. }
+            | CompareError::Decode { path, .. }
+            | CompareError::Template { path, .. } => path.as_deref(),
+            CompareError::InvalidPathMapping { .. }
+            | CompareError::UnsupportedFormat { .. }
+            | CompareError::InvalidOption { .. }
+            | CompareError::Serialize { .. }
+            | CompareError::Render { .. }
+            | CompareError::ThreadPool { .. } => None,
+        }
+    }
+
+    /// Attach the image a decode or diff error came from. Errors that
+    /// already name a path are returned unchanged.
+    pub(crate) fn for_image(mut self, name: &Path) -> Self {
+        if let CompareError::Decode { path, .. } | CompareError::Diff { path, .. } = &mut self {
+            path.get_or_insert_with(|| name.to_path_buf());
+        }
+        self
+    }
+}
+
+impl From<ImageDiffError> for CompareError {
+    fn from(source: ImageDiffError) -> Self {
+        CompareError::Diff { path: None, source }
+    }
+}
+
+/// Serialisable summary of a [`CompareError`], as printed by the CLI and
+/// returned from the Wasm entry point.
+#[derive(Debug, Serialize)]
+pub struct ErrorInfo {
+    pub code: &'static str,
+    pub stage: Stage,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+impl From<&CompareError> for ErrorInfo {
+    fn from(e: &CompareError) -> Self {
+        ErrorInfo {
+            code: e.code(),
+            stage: e.stage(),
+            message: e.to_string(),
+            path: e.path().map(Path::to_path_buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_errors_pick_up_the_image_name() {
+        let err = CompareError::Decode {
+            format: "png",
+            path: None,
+            reason: "bad header".to_string(),
+        }
+        .for_image(Path::new("a/b.png"));
+        assert_eq!(err.to_string(), "cannot decode png image for a/b.png: bad header");
+
+        let info = ErrorInfo::from(&err);
+        assert_eq!(info.code, "DECODE_FAILED");
+        assert_eq!(info.stage, Stage::Decode);
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            serde_json::json!({
+                "code": "DECODE_FAILED",
+                "stage": "decode",
+                "message": "cannot decode png image for a/b.png: bad header",
+                "path": "a/b.png",
+            })
+        );
+    }
+
+    #[test]
+    fn untargeted_errors_have_no_path() {
+        let err = CompareError::InvalidOption {
+            option: "diffDim",
+            reason: "out of range".to_string(),
+        };
+        assert_eq!(err.stage(), Stage::Config);
+        assert!(err.path().is_none());
+    }
+}
@@ -0,0 +1,276 @@
+//! Image formats recognised during discovery.
+//!
+//! `image-diff-rs` decodes the classic set (PNG, JPEG, GIF, BMP, TIFF, WebP)
+//! itself. AVIF and SVG are transcoded to PNG first so the diff sees plain
+//! pixels: SVG is rasterised with resvg at `Options::svg_scale` (the `svg`
+//! feature, on by default), AVIF is decoded with dav1d (the `avif` feature,
+//! off by default because it needs the native library and doesn't build for
+//! Wasm).
+//!
+//! Discovery goes by extension unless `Options::sniff_content` is set, in
+//! which case [`sniff`] classifies each file by its leading bytes instead.
+
+use std::{fs::File, io::Read, path::Path};
+
+use crate::CompareError;
+
+/// Bytes read per file when sniffing. Binary formats only need the first
+/// dozen; SVG may sit behind an XML declaration, comments or a doctype.
+const SNIFF_LEN: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Tiff,
+    Webp,
+    Avif,
+    Svg,
+}
+
+impl ImageFormat {
+    /// What classic reg-cli picks up, and the default for `Options::formats`.
+    pub const CLASSIC: &'static [ImageFormat] = &[
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::Gif,
+        ImageFormat::Bmp,
+        ImageFormat::Tiff,
+        ImageFormat::Webp,
+    ];
+
+    /// Lowercase extensions claimed by this format.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Png => &["png"],
+            ImageFormat::Jpeg => &["jpg", "jpeg"],
+            ImageFormat::Gif => &["gif"],
+            ImageFormat::Bmp => &["bmp"],
+            ImageFormat::Tiff => &["tiff"],
+            ImageFormat::Webp => &["webp"],
+            ImageFormat::Avif => &["avif"],
+            ImageFormat::Svg => &["svg"],
+        }
+    }
+
+    /// MIME type for data URLs in the self-contained report.
+    pub(crate) fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::Svg => "image/svg+xml",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        const ALL: [ImageFormat; 8] = [
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Gif,
+            ImageFormat::Bmp,
+            ImageFormat::Tiff,
+            ImageFormat::Webp,
+            ImageFormat::Avif,
+            ImageFormat::Svg,
+        ];
+        // ASCII case-insensitive compare is enough: every extension above
+        // is lowercase ASCII, and it avoids a `to_lowercase()` allocation
+        // per directory entry.
+        ALL.into_iter()
+            .find(|f| f.extensions().iter().any(|e| ext.eq_ignore_ascii_case(e)))
+    }
+
+    /// Parse a CLI format name (any of its extensions). Formats whose
+    /// decoder wasn't compiled in are rejected here rather than failing
+    /// every image later.
+    pub fn parse(name: &str) -> Result<Self, CompareError> {
+        let format = Self::from_extension(name.trim()).ok_or_else(|| {
+            CompareError::UnsupportedFormat {
+                format: name.to_string(),
+                reason: "unknown image format".to_string(),
+            }
+        })?;
+        if !format.is_available() {
+            return Err(CompareError::UnsupportedFormat {
+                format: name.to_string(),
+                reason: format!("reg-cli was built without the `{}` feature", format.name()),
+            });
+        }
+        Ok(format)
+    }
+
+    pub fn name(self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    /// Whether this build can decode the format.
+    pub fn is_available(self) -> bool {
+        (self != ImageFormat::Avif || cfg!(feature = "avif"))
+            && (self != ImageFormat::Svg || cfg!(feature = "svg"))
+    }
+}
+
+/// Identify an image from its leading bytes.
+pub(crate) fn sniff(buf: &[u8]) -> Option<ImageFormat> {
+    if buf.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(ImageFormat::Png);
+    }
+    if buf.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some(ImageFormat::Jpeg);
+    }
+    if buf.starts_with(b"GIF87a") || buf.starts_with(b"GIF89a") {
+        return Some(ImageFormat::Gif);
+    }
+    if buf.starts_with(b"BM") && buf.len() >= 26 {
+        return Some(ImageFormat::Bmp);
+    }
+    if buf.starts_with(b"II*\0") || buf.starts_with(b"MM\0*") {
+        return Some(ImageFormat::Tiff);
+    }
+    if buf.starts_with(b"RIFF") && buf.get(8..12) == Some(&b"WEBP"[..]) {
+        return Some(ImageFormat::Webp);
+    }
+    if buf.get(4..8) == Some(&b"ftyp"[..]) && matches!(buf.get(8..12), Some(b"avif" | b"avis")) {
+        return Some(ImageFormat::Avif);
+    }
+    let head = &buf[..buf.len().min(SNIFF_LEN as usize)];
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    if text.starts_with('<') && text.contains("<svg") {
+        return Some(ImageFormat::Svg);
+    }
+    None
+}
+
+/// [`sniff`] the file at `path`. Unreadable files are `None`, so the caller
+/// falls back to the extension and the read error surfaces at diff time.
+pub(crate) fn sniff_file(path: &Path) -> Option<ImageFormat> {
+    let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+    File::open(path).ok()?.take(SNIFF_LEN).read_to_end(&mut head).ok()?;
+    sniff(&head)
+}
+
+/// The formats `image-diff-rs` can't decode and [`prepare`] transcodes.
+/// Buffers reach the diff without a path, so this always goes by content.
+fn transcoded_format(buf: &[u8]) -> Option<ImageFormat> {
+    sniff(buf).filter(|f| matches!(f, ImageFormat::Avif | ImageFormat::Svg))
+}
+
+/// Hand `buf` back unchanged when `image-diff-rs` can decode it, or as PNG
+/// when it's AVIF or SVG.
+pub(crate) fn prepare(buf: Vec<u8>, svg_scale: f32) -> Result<Vec<u8>, CompareError> {
+    let Some(format) = transcoded_format(&buf) else {
+        return Ok(buf);
+    };
+    let decode_error = |reason: String| CompareError::Decode {
+        format: format.name(),
+        path: None,
+        reason,
+    };
+    match format {
+        ImageFormat::Svg => rasterize_svg(&buf, svg_scale).map_err(decode_error),
+        ImageFormat::Avif => decode_avif(&buf).map_err(decode_error),
+        _ => Ok(buf),
+    }
+}
+
+#[cfg(feature = "svg")]
+fn rasterize_svg(buf: &[u8], scale: f32) -> Result<Vec<u8>, String> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(buf, &usvg::Options::default()).map_err(|e| e.to_string())?;
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or_else(|| format!("invalid scale {scale}"))?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| format!("cannot allocate {}x{} canvas", size.width(), size.height()))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "svg"))]
+fn rasterize_svg(_buf: &[u8], _scale: f32) -> Result<Vec<u8>, String> {
+    Err("reg-cli was built without the `svg` feature".to_string())
+}
+
+#[cfg(feature = "avif")]
+fn decode_avif(buf: &[u8]) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory_with_format(buf, image::ImageFormat::Avif)
+        .map_err(|e| e.to_string())?;
+    let mut png = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png.into_inner())
+}
+
+#[cfg(not(feature = "avif"))]
+fn decode_avif(_buf: &[u8]) -> Result<Vec<u8>, String> {
+    Err("reg-cli was built without the `avif` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extensions_are_case_insensitive() {
+        assert_eq!(ImageFormat::from_extension("JPEG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_extension("Svg"), Some(ImageFormat::Svg));
+        assert_eq!(ImageFormat::from_extension("txt"), None);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_names() {
+        assert!(matches!(
+            ImageFormat::parse("heic"),
+            Err(CompareError::UnsupportedFormat { .. })
+        ));
+        assert_eq!(ImageFormat::parse("jpg").unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn sniffs_only_transcoded_formats() {
+        assert_eq!(
+            transcoded_format(b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<svg/>"),
+            Some(ImageFormat::Svg)
+        );
+        assert_eq!(
+            transcoded_format(b"\0\0\0\x1cftypavif\0\0\0\0"),
+            Some(ImageFormat::Avif)
+        );
+        assert_eq!(transcoded_format(b"\x89PNG\r\n\x1a\n"), None);
+    }
+
+    #[test]
+    fn sniffs_binary_formats() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0"), Some(ImageFormat::Png));
+        assert_eq!(sniff(&[0xff, 0xd8, 0xff, 0xe0]), Some(ImageFormat::Jpeg));
+        assert_eq!(sniff(b"GIF89a\x01\0"), Some(ImageFormat::Gif));
+        assert_eq!(sniff(b"MM\0*\0\0\0\x08"), Some(ImageFormat::Tiff));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8L"), Some(ImageFormat::Webp));
+        assert_eq!(sniff(b"BM too short"), None);
+        assert_eq!(sniff(b"hello"), None);
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn svg_is_rasterised_at_scale() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="3"><rect width="4" height="3" fill="red"/></svg>"#;
+        let png = prepare(svg.to_vec(), 2.0).unwrap();
+        assert_eq!(crate::schedule::image_dimensions(&png), Some((8, 6)));
+    }
+}
@@ -1,45 +1,42 @@
+mod animation;
+mod bundle;
+mod color;
+mod comparer;
+mod diff_style;
 mod dir;
+mod embed;
+mod error;
+mod format;
+mod manifest;
+mod mapping;
+mod output;
+mod prune;
 mod report;
+mod schedule;
+mod thumbnail;
 pub mod tracing_layer;
 
-use image_diff_rs::{DiffOption, DiffOutput, EncodeFormat, ImageDiffError};
-use rayon::{prelude::*, ThreadPoolBuilder};
+use image_diff_rs::EncodeFormat;
 use report::create_reports;
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
 };
 use tracing::{info, info_span, instrument};
 
-use thiserror::Error;
-
-pub use report::JsonReport;
+pub use color::{parse_hex_color, AlphaMode};
+pub use comparer::{compare_buffers, compare_files, Comparer, ImagePair, PairOutcome, PairResult};
+pub use diff_style::DiffStyle;
+pub use error::{CompareError, ErrorInfo, Stage};
+pub use format::ImageFormat;
+pub use manifest::{Manifest, ManifestEntry};
+pub use mapping::PathMapping;
+pub use output::{write_output, Artifact};
+pub use prune::CleanDiffDir;
+pub use report::{FormatMismatch, FrameReport, JsonReport, RenamedItem, ReportMetadata};
 pub use tracing_layer::{clear_trace_data, get_trace_data_json, init_tracing, set_js_trace_context, SpanData, TraceData};
 pub use url::*;
 
-#[derive(Error, Debug)]
-pub enum CompareError {
-    #[error("file io error, {0}")]
-    File(#[from] std::io::Error),
-    #[error("image diff error, {0}")]
-    ImageDiff(#[from] ImageDiffError),
-    #[error("unknown error")]
-    Unknown,
-}
-
-static SUPPORTED_EXTENTIONS: [&str; 7] = ["tiff", "jpeg", "jpg", "gif", "png", "bmp", "webp"];
-
-/// Per-file outcome of the parallel diff loop. We never propagate
-/// per-image errors out of the rayon closure — instead each failure is
-/// logged to stderr, fired as a `compare-event` of kind "fail", and
-/// folded into the `failedItems` bucket downstream. This matches classic
-/// reg-cli's tolerance (it forks per image, so a single corrupt PNG
-/// can't sink the whole batch).
-enum ImageOutcome {
-    Ok(DiffOutput),
-    Failed,
-}
-
 static DEFAULT_JSON_PATH: &'static str = "./reg.json";
 static DEFAULT_REPORT_PATH: &'static str = "./report.html";
 
@@ -103,18 +100,60 @@ fn encode_json_string(out: &mut String, s: &str) {
     }
 }
 
-fn is_supported_extension(path: &Path) -> bool {
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            // Avoid `to_lowercase()` allocation: ASCII case-insensitive
-            // compare is enough — every entry in SUPPORTED_EXTENTIONS is
-            // lowercase ASCII.
-            return SUPPORTED_EXTENTIONS
-                .iter()
-                .any(|e| ext_str.eq_ignore_ascii_case(e));
+fn extension_format(path: &Path) -> Option<ImageFormat> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(ImageFormat::from_extension)
+}
+
+/// Directory-walk policy for `find_images`, resolved from [`Options`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WalkOptions<'a> {
+    pub(crate) follow_symlinks: bool,
+    pub(crate) skip_hidden: bool,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) formats: &'a [ImageFormat],
+    pub(crate) report_unsupported: bool,
+    pub(crate) sniff_content: bool,
+}
+
+impl Default for WalkOptions<'_> {
+    fn default() -> Self {
+        Self {
+            follow_symlinks: false,
+            skip_hidden: false,
+            max_depth: None,
+            formats: ImageFormat::CLASSIC,
+            report_unsupported: false,
+            sniff_content: false,
+        }
+    }
+}
+
+impl<'a> WalkOptions<'a> {
+    pub(crate) fn from_options(options: &Options<'a>) -> Self {
+        Self {
+            follow_symlinks: options.follow_symlinks.unwrap_or(false),
+            skip_hidden: options.skip_hidden.unwrap_or(false),
+            max_depth: options.max_depth,
+            formats: options.formats.unwrap_or(ImageFormat::CLASSIC),
+            report_unsupported: options.report_unsupported.unwrap_or(false),
+            sniff_content: options.sniff_content.unwrap_or(false),
         }
     }
-    false
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Walk {
+    pub(crate) images: BTreeSet<PathBuf>,
+    /// Directories (including the root) that could not be opened or listed.
+    pub(crate) unreadable: Vec<(PathBuf, std::io::Error)>,
+    /// Files skipped because their extension isn't an enabled format. Only
+    /// collected with `WalkOptions::report_unsupported`.
+    pub(crate) unsupported: BTreeSet<PathBuf>,
+    /// Images whose sniffed format disagrees with their extension, as
+    /// (relative path, extension, sniffed format).
+    pub(crate) mismatched: Vec<(PathBuf, String, ImageFormat)>,
 }
 
 #[derive(Debug)]
@@ -123,6 +162,13 @@ pub(crate) struct DetectedImages {
     pub(crate) actual: BTreeSet<PathBuf>,
     pub(crate) deleted: BTreeSet<PathBuf>,
     pub(crate) new: BTreeSet<PathBuf>,
+    /// Files on either side that were skipped for their extension.
+    pub(crate) unsupported: BTreeSet<PathBuf>,
+    pub(crate) format_mismatches: BTreeSet<FormatMismatch>,
+    /// Items whose file has another relative path than their name (pairs
+    /// from a manifest or `path_mappings`), item name → file, per side.
+    pub(crate) actual_paths: BTreeMap<PathBuf, PathBuf>,
+    pub(crate) expected_paths: BTreeMap<PathBuf, PathBuf>,
 }
 
 /// Options for configuring the comparison process.
@@ -133,12 +179,63 @@ pub(crate) struct DetectedImages {
 /// * `threshold_rate` - An optional threshold rate for comparison.
 /// * `threshold_pixel` - An optional threshold pixel count for comparison.
 /// * `enable_antialias` - An optional flag to enable or disable antialiasing.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Options<'a> {
     pub report: Option<&'a Path>,
     /// Where to write the JUnit XML report. `None` means no junit output.
     pub junit_report: Option<&'a Path>,
     pub json: Option<&'a Path>,
+    /// Pair list to compare instead of mirroring `actual_dir` against
+    /// `expected_dir` by relative path. See [`Manifest`] for the format.
+    pub manifest: Option<&'a Path>,
+    /// Rewrite rules from actual-relative to expected-relative paths, tried
+    /// in order. Ignored when `manifest` is set (it pairs explicitly).
+    pub path_mappings: Option<&'a [PathMapping]>,
+    /// Pair each deleted image with a new image whose contents are identical
+    /// or within `threshold_*`, and report them as `renamedItems` instead.
+    pub detect_renames: Option<bool>,
+    /// Descend into symlinked directories while walking (symlinked files
+    /// are always included). Loops are detected and skipped.
+    pub follow_symlinks: Option<bool>,
+    /// Skip files and directories whose name starts with `.`.
+    pub skip_hidden: Option<bool>,
+    /// How many directory levels below each root to walk. `Some(0)` only
+    /// looks at the root itself; `None` is unlimited.
+    pub max_depth: Option<usize>,
+    /// Treat a missing `expected_dir` as empty instead of failing, for
+    /// first-time baseline creation. A missing `actual_dir`, or any
+    /// directory that exists but can't be read, is still an error.
+    pub allow_empty_expected: Option<bool>,
+    /// Pair actual and expected images whose relative paths differ only in
+    /// letter case or Unicode normalisation (NFC vs NFD, as macOS writes
+    /// them). Two files on the same side that fold to the same path are a
+    /// `PathCollision` error. Ignored when `manifest` is set.
+    pub case_insensitive_paths: Option<bool>,
+    /// Formats picked up while walking. `None` means
+    /// [`ImageFormat::CLASSIC`]; AVIF and SVG have to be opted into.
+    pub formats: Option<&'a [ImageFormat]>,
+    /// Scale factor for rasterising SVG images. `None` is 1.0 (the SVG's
+    /// own width/height in pixels).
+    pub svg_scale: Option<f32>,
+    /// List files skipped for their extension as `unsupportedItems` in
+    /// reg.json (and warn about them) instead of ignoring them silently.
+    pub report_unsupported: Option<bool>,
+    /// Classify files by their leading bytes rather than their extension,
+    /// so extensionless or misnamed images are still picked up. Reads the
+    /// head of every file in the tree. Images whose content disagrees with
+    /// their extension are listed as `formatMismatches` in reg.json.
+    pub sniff_content: Option<bool>,
+    /// Diff animated GIF / APNG / WebP frame by frame instead of only the
+    /// first frame. Results land in `frameItems`; a pair whose frame counts
+    /// differ fails and is listed in `frameCountMismatchItems`.
+    pub compare_frames: Option<bool>,
+    /// How transparency is treated before diffing. `None` compares RGBA as
+    /// decoded. Recorded under `metadata` in reg.json.
+    pub alpha_mode: Option<AlphaMode>,
+    /// Convert images with an embedded ICC profile to sRGB before diffing,
+    /// so the same pixels tagged differently compare equal. Recorded under
+    /// `metadata` in reg.json.
+    pub normalize_srgb: Option<bool>,
     /// Mirror of classic reg-cli's `-E / --extendedErrors`. Affects only the
     /// JUnit XML: new/deleted items become `<failure message="newItem"/>` /
     /// `"deletedItem"` instead of silent passed testcases. Non-junit exit
@@ -149,12 +246,70 @@ pub struct Options<'a> {
     pub matching_threshold: Option<f32>,
     pub threshold_rate: Option<f32>,
     pub threshold_pixel: Option<u64>,
+    /// Number of diff worker threads. `None` picks a count from the total
+    /// pixel area of the suite, capped at the host's available parallelism.
+    /// An explicit value is always used as-is, even for tiny suites.
     pub concurrency: Option<usize>,
     pub enable_antialias: Option<bool>,
     /// Format for the generated diff images. `None` keeps the default
     /// (WebP lossless). Setting `Some(Png)` makes the output apples-to-apples
     /// with the classic JS implementation.
     pub diff_image_format: Option<DiffImageFormat>,
+    /// Draw diff images with a custom highlight colour, dimming and/or as an
+    /// `expected | actual | diff` strip. `None` keeps the diff engine's own
+    /// image.
+    pub diff_style: Option<DiffStyle>,
+    /// Also write diff images for pairs that differ but pass under
+    /// `threshold_pixel` / `threshold_rate`, and list them as
+    /// `toleratedItems` in reg.json and the HTML report. Their diffs are
+    /// not added to `diffItems`, which stays failures-only.
+    pub write_tolerated_diffs: Option<bool>,
+    /// Remove diff images written by the previous run with this option,
+    /// which it tracked in a `.reg-cli-diffs.json` manifest in the diff dir.
+    /// Files reg-cli didn't record are left alone.
+    pub clean_diff_dir: Option<CleanDiffDir>,
+    /// Mustache template to render the HTML report with instead of the
+    /// built-in one. It gets the same data (`js`, `css`, `faviconData`,
+    /// `report`) plus the report fields as mustache values and `metrics`
+    /// with per-status counts.
+    pub template: Option<&'a Path>,
+    /// Write the report UI's JS, CSS and favicon into this directory under
+    /// content-hashed names and link them from the HTML instead of inlining
+    /// them, so they can be cached across builds.
+    pub report_assets_dir: Option<&'a Path>,
+    /// Embed the actual, expected and diff images in the HTML report as
+    /// data URLs, so it keeps working when moved or uploaded on its own.
+    pub embed_images: Option<bool>,
+    /// With `embed_images`, embed images larger than this many pixels on
+    /// their longest side as PNG thumbnails. The full-size image is loaded
+    /// from its relative path when the report shows it enlarged.
+    pub embed_thumbnail_size: Option<u32>,
+    /// Also write a portable copy of the report here: the HTML report,
+    /// reg.json and only the images they list, with paths rewritten to
+    /// match. A `.tar` or `.zip` path writes an archive, anything else a
+    /// directory.
+    pub bundle: Option<&'a Path>,
+    /// Inline at most this many passed items in the HTML report; `0` leaves
+    /// them out. reg.json still lists all of them, the report payload gets
+    /// `totals` with the real counts and the report says how many are left
+    /// out.
+    pub report_passed_limit: Option<usize>,
+    /// Inline at most this many passed, renamed and tolerated items in the
+    /// HTML report and write the rest as JSON pages under
+    /// `<report stem>.pages/`, listed in the payload's `pages` for
+    /// `window['__reg_pages__']` to fetch. Failed, new and deleted items are
+    /// always inlined. The report notes how many items it leaves out.
+    pub report_page_size: Option<usize>,
+    /// Write a thumbnail of every actual, expected and diff image the
+    /// report lists under this directory and have the report show them in
+    /// place of full-size screenshots, loading an original only when it is
+    /// drawn larger than its thumbnail. Ignored by embedded reports and
+    /// `run_from_json`, which writes no images.
+    pub thumbnail_dir: Option<&'a Path>,
+    /// Longest side of thumbnails in pixels. Defaults to 320.
+    pub thumbnail_size: Option<u32>,
+    /// Thumbnail encoding. Defaults to WebP.
+    pub thumbnail_format: Option<DiffImageFormat>,
     /// Mirror of classic reg-cli's `--additionalDetection client`. When set,
     /// the HTML report's `ximgdiffConfig.enabled` is `true` and the report UI
     /// runs a second-pass pixel detector in the browser.
@@ -180,7 +335,7 @@ impl From<DiffImageFormat> for EncodeFormat {
 }
 
 impl DiffImageFormat {
-    fn extension(self) -> &'static str {
+    pub(crate) fn extension(self) -> &'static str {
         match self {
             DiffImageFormat::Webp => "webp",
             DiffImageFormat::Png => "png",
@@ -194,20 +349,50 @@ impl<'a> Default for Options<'a> {
             report: None,
             junit_report: None,
             json: Some(Path::new(DEFAULT_JSON_PATH)),
+            manifest: None,
+            path_mappings: None,
+            detect_renames: None,
+            follow_symlinks: None,
+            skip_hidden: None,
+            max_depth: None,
+            allow_empty_expected: None,
+            case_insensitive_paths: None,
+            formats: None,
+            svg_scale: None,
+            report_unsupported: None,
+            sniff_content: None,
+            compare_frames: None,
+            alpha_mode: None,
+            normalize_srgb: None,
             extended_errors: None,
             url_prefix: None,
             matching_threshold: Some(0.0),
             threshold_rate: None,
             threshold_pixel: None,
-            concurrency: Some(4),
+            concurrency: None,
             enable_antialias: None,
             diff_image_format: None,
+            diff_style: None,
+            write_tolerated_diffs: None,
+            clean_diff_dir: None,
+            template: None,
+            report_assets_dir: None,
+            embed_images: None,
+            embed_thumbnail_size: None,
+            bundle: None,
+            report_passed_limit: None,
+            report_page_size: None,
+            thumbnail_dir: None,
+            thumbnail_size: None,
+            thumbnail_format: None,
             enable_client_additional_detection: None,
         }
     }
 }
 
-/// Runs the comparison process.
+/// Runs the comparison process. One-shot convenience over
+/// [`Comparer::compare_dirs`]; build a `Comparer` directly when comparing
+/// several directory pairs with the same options.
 ///
 /// # Arguments
 ///
@@ -222,308 +407,7 @@ pub fn run(
     diff_dir: impl AsRef<Path>,
     options: Options,
 ) -> Result<JsonReport, CompareError> {
-    let actual_dir = actual_dir.as_ref();
-    let expected_dir = expected_dir.as_ref();
-    let diff_dir = diff_dir.as_ref();
-    let json_path = options.json.unwrap_or_else(|| Path::new(DEFAULT_JSON_PATH));
-    let report = options
-        .report
-        .unwrap_or_else(|| Path::new(DEFAULT_REPORT_PATH));
-
-    info!(
-        actual_dir = %actual_dir.display(),
-        expected_dir = %expected_dir.display(),
-        diff_dir = %diff_dir.display(),
-        "Starting image comparison"
-    );
-
-    let detected = find_images(&expected_dir, &actual_dir);
-
-    // Emit `new` / `delete` progress events up front — classic reg-cli
-    // fires these before the per-image diff loop starts, and reg-suit /
-    // spinners depend on live progress. See `emit_progress` for the wire
-    // format and `js/entry.ts` for the receiving side.
-    for p in &detected.new {
-        emit_progress("new", &p.display().to_string());
-    }
-    for p in &detected.deleted {
-        emit_progress("delete", &p.display().to_string());
-    }
-
-    let targets: Vec<PathBuf> = detected
-        .actual
-        .intersection(&detected.expected)
-        .cloned()
-        .collect();
-
-    // Match classic reg-cli (src/index.js:77): for small image sets the
-    // rayon thread-pool spin-up + cross-thread span dance costs more than
-    // any parallelism buys. Force single-threaded until we cross the
-    // classic's 20-image threshold.
-    let concurrency = if targets.len() < 20 {
-        1
-    } else {
-        options.concurrency.unwrap_or(4)
-    };
-    info!(target_count = targets.len(), concurrency, "Starting parallel image diff");
-
-    let pool = {
-        let _pool_span = info_span!("build_thread_pool", num_threads = concurrency).entered();
-        ThreadPoolBuilder::new()
-            .num_threads(concurrency)
-            .build()
-            .unwrap()
-    };
-
-    let result = {
-        let diff_span = info_span!("parallel_image_diff", target_count = targets.len());
-        let _diff_guard = diff_span.enter();
-        
-        // Capture the parent span to propagate to rayon threads
-        let parent_span = diff_span.clone();
-        
-        pool.install(|| {
-            // Note: There may be ~20-30ms delay here due to rayon thread scheduling overhead
-            // This is especially noticeable in WASI environments
-            targets
-                .par_iter()
-                .map(|path| {
-                    // Explicitly set parent span for cross-thread context propagation
-                    let image_span = info_span!(parent: parent_span.clone(), "diff_single_image", image = %path.display());
-                    let _image_guard = image_span.enter();
-
-                    let actual_path = actual_dir.join(path);
-                    let expected_path = expected_dir.join(path);
-
-                    // Per-file failure policy: read OR decode errors are
-                    // logged to stderr, classified as "fail" via a live
-                    // compare-event, and counted into `failedItems`. We
-                    // never propagate them up — one corrupt PNG must not
-                    // abort a 1000-image batch (parity with classic
-                    // reg-cli, which forks-per-image and tolerates child
-                    // crashes individually).
-                    let img1 = match std::fs::read(&actual_path) {
-                        Ok(b) => b,
-                        Err(e) => {
-                            eprintln!(
-                                "[reg-cli] failed to read actual {}: {}",
-                                actual_path.display(),
-                                e
-                            );
-                            emit_progress("fail", &path.display().to_string());
-                            return (path.clone(), ImageOutcome::Failed);
-                        }
-                    };
-                    let img2 = match std::fs::read(&expected_path) {
-                        Ok(b) => b,
-                        Err(e) => {
-                            eprintln!(
-                                "[reg-cli] failed to read expected {}: {}",
-                                expected_path.display(),
-                                e
-                            );
-                            emit_progress("fail", &path.display().to_string());
-                            return (path.clone(), ImageOutcome::Failed);
-                        }
-                    };
-
-                    let res = match image_diff_rs::diff(
-                        img1,
-                        img2,
-                        &DiffOption {
-                            threshold: options.matching_threshold,
-                            include_anti_alias: Some(!options.enable_antialias.unwrap_or_default()),
-                            encode_format: options
-                                .diff_image_format
-                                .map(EncodeFormat::from),
-                        },
-                    ) {
-                        Ok(r) => r,
-                        Err(e) => {
-                            let path_str = path.display().to_string();
-                            eprintln!("[reg-cli] failed to diff {}: {}", path_str, e);
-                            emit_progress("fail", &path_str);
-                            return (path.clone(), ImageOutcome::Failed);
-                        }
-                    };
-
-                    // Fire the live pass/fail event as early as we can —
-                    // right after the pixel-diff completes, before the
-                    // caller-thread serialises through `collect`. Classify
-                    // here (not in the post-collect loop) so consumers see
-                    // progress while other rayon threads are still working
-                    // on remaining images.
-                    let kind = match &res {
-                        DiffOutput::Eq => "pass",
-                        DiffOutput::NotEq {
-                            diff_count,
-                            width,
-                            height,
-                            ..
-                        } => {
-                            if is_passed(
-                                *width,
-                                *height,
-                                *diff_count as u64,
-                                options.threshold_pixel,
-                                options.threshold_rate,
-                            ) {
-                                "pass"
-                            } else {
-                                "fail"
-                            }
-                        }
-                    };
-                    // Avoid the second `path.display().to_string()` allocation
-                    // by using `to_string_lossy()` which borrows on UTF-8 paths.
-                    emit_progress(kind, &path.to_string_lossy());
-
-                    (path.clone(), ImageOutcome::Ok(res))
-                })
-                .collect::<Vec<(PathBuf, ImageOutcome)>>()
-        })
-    };
-
-    let mut differences = BTreeSet::new();
-    let mut passed = BTreeSet::new();
-    let mut failed = BTreeSet::new();
-
-    for (image_name, item) in result {
-        match item {
-            ImageOutcome::Failed => {
-                // Per-file read/decode failure: count as failed but
-                // don't try to write a diff image (we have no pixels).
-                failed.insert(image_name);
-            }
-            ImageOutcome::Ok(DiffOutput::Eq) => {
-                passed.insert(image_name);
-            }
-            ImageOutcome::Ok(DiffOutput::NotEq {
-                diff_count,
-                diff_image,
-                width,
-                height,
-            }) => {
-                if is_passed(
-                    width,
-                    height,
-                    diff_count as u64,
-                    options.threshold_pixel,
-                    options.threshold_rate,
-                ) {
-                    passed.insert(image_name);
-                } else {
-                    let mut diff_image_name = image_name.clone();
-                    diff_image_name.set_extension(
-                        options
-                            .diff_image_format
-                            .unwrap_or_default()
-                            .extension(),
-                    );
-                    let diff_path = diff_dir.join(&diff_image_name);
-                    if let Some(parent) = diff_path.parent() {
-                        std::fs::create_dir_all(parent).map_err(|e| {
-                            eprintln!("Failed to create diff directory: {:?}, error: {:?}", parent, e);
-                            e
-                        })?;
-                    }
-                    std::fs::write(&diff_path, diff_image).map_err(|e| {
-                        eprintln!("Failed to write diff file: {:?}, error: {:?}", diff_path, e);
-                        e
-                    })?;
-                    failed.insert(image_name);
-                    differences.insert(diff_image_name);
-                }
-            }
-        }
-    }
-
-    let report = {
-        let _report_span = info_span!("create_reports").entered();
-        info!(
-            passed_count = passed.len(),
-            failed_count = failed.len(),
-            new_count = detected.new.len(),
-            deleted_count = detected.deleted.len(),
-            "Creating reports"
-        );
-        create_reports(report::ReportInput {
-            passed,
-            failed,
-            new: detected.new,
-            deleted: detected.deleted,
-            actual: detected.actual,
-            expected: detected.expected,
-            report,
-            differences,
-            json: json_path,
-            actual_dir,
-            expected_dir,
-            diff_dir,
-            from_json: false,
-            url_prefix: options.url_prefix,
-            diff_image_extention: options
-                .diff_image_format
-                .unwrap_or_default()
-                .extension(),
-            enable_client_additional_detection: options
-                .enable_client_additional_detection
-                .unwrap_or(false),
-        })
-    };
-
-    if let (Some(html), Some(report_path)) = (report.html, options.report) {
-        let _write_span = info_span!("write_report", path = %report_path.display()).entered();
-        if let Some(parent) = report_path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| {
-                eprintln!("Failed to create report directory: {:?}, error: {:?}", parent, e);
-                e
-            })?;
-        }
-        std::fs::write(report_path, html).map_err(|e| {
-            eprintln!("Failed to write report file: {:?}, error: {:?}", report_path, e);
-            e
-        })?;
-        info!(path = %report_path.display(), "Report written");
-    };
-
-    // Persist reg.json to disk. Previously this was done on the JS side after
-    // the Wasm returned the string; moving it to Rust makes the contract
-    // symmetric with HTML / diff images (reg_core writes every artefact it
-    // knows how to produce) and keeps the non-wasm `cargo run` CLI useful.
-    {
-        let _write_span = info_span!("write_json", path = %json_path.display()).entered();
-        if let Some(parent) = json_path.parent() {
-            std::fs::create_dir_all(parent).ok();
-        }
-        let body = serde_json::to_string_pretty(&report.json).map_err(|e| {
-            eprintln!("Failed to serialize reg.json: {:?}", e);
-            std::io::Error::new(std::io::ErrorKind::Other, e)
-        })?;
-        std::fs::write(json_path, body + "\n").map_err(|e| {
-            eprintln!("Failed to write {:?}: {:?}", json_path, e);
-            e
-        })?;
-    }
-
-    // JUnit XML (optional).
-    if let Some(junit_path) = options.junit_report {
-        let _write_span = info_span!("write_junit", path = %junit_path.display()).entered();
-        if let Some(parent) = junit_path.parent() {
-            std::fs::create_dir_all(parent).ok();
-        }
-        let xml = report::build_junit_xml(
-            &report.json,
-            options.extended_errors.unwrap_or(false),
-        );
-        std::fs::write(junit_path, xml).map_err(|e| {
-            eprintln!("Failed to write {:?}: {:?}", junit_path, e);
-            e
-        })?;
-    }
-
-    info!("Comparison complete");
-    Ok(report.json)
+    Comparer::new(options).compare_dirs(actual_dir, expected_dir, diff_dir)
 }
 
 /// Re-render the HTML report from an existing `reg.json` WITHOUT running any
@@ -537,16 +421,31 @@ pub fn run_from_json(
     options: Options,
 ) -> Result<JsonReport, CompareError> {
     let _root = info_span!("run_from_json", path = %json_path.as_ref().display()).entered();
-    let content = std::fs::read_to_string(json_path.as_ref())?;
-    let json: JsonReport = serde_json::from_str(&content).map_err(|e| {
-        eprintln!("Failed to parse {:?}: {:?}", json_path.as_ref(), e);
-        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    let json_path = json_path.as_ref();
+    let content = std::fs::read_to_string(json_path).map_err(|source| CompareError::Read {
+        path: json_path.to_path_buf(),
+        source,
     })?;
+    let json: JsonReport =
+        serde_json::from_str(&content).map_err(|source| CompareError::InvalidReportJson {
+            path: json_path.to_path_buf(),
+            source,
+        })?;
 
     let report_path = options
         .report
         .unwrap_or_else(|| Path::new(DEFAULT_REPORT_PATH));
     let out_json_path = options.json.unwrap_or_else(|| Path::new(DEFAULT_JSON_PATH));
+    // Images are only read to embed or bundle them; otherwise the
+    // directories don't have to be traceable back to disk.
+    let [actual_dir, expected_dir, diff_dir] =
+        if options.embed_images.unwrap_or(false) || options.bundle.is_some() {
+            let [actual, expected, diff] = [&json.actual_dir, &json.expected_dir, &json.diff_dir]
+                .map(|dir| local_dir(json_path, dir, options.url_prefix.as_ref()));
+            [actual?, expected?, diff?]
+        } else {
+            Default::default()
+        };
 
     // Rebuild ReportInput fields from the parsed JsonReport. Note: the JSON's
     // actualDir / expectedDir / diffDir are already strings the template
@@ -554,52 +453,63 @@ pub fn run_from_json(
     // rather than re-resolving via `resolve_dir`.
     let report = {
         let _s = info_span!("create_reports_from_json").entered();
-        create_reports(report::ReportInput {
-            passed: json.passed_items.clone(),
-            failed: json.failed_items.clone(),
-            new: json.new_items.clone(),
-            deleted: json.deleted_items.clone(),
-            actual: json.actual_items.clone(),
-            expected: json.expected_items.clone(),
-            differences: json.diff_items.clone(),
-            report: report_path,
-            json: out_json_path,
-            actual_dir: Path::new(&json.actual_dir),
-            expected_dir: Path::new(&json.expected_dir),
-            diff_dir: Path::new(&json.diff_dir),
-            from_json: true,
-            url_prefix: options.url_prefix,
-            diff_image_extention: options
-                .diff_image_format
-                .unwrap_or_default()
-                .extension(),
-            enable_client_additional_detection: options
-                .enable_client_additional_detection
-                .unwrap_or(false),
-        })
+        let mut input =
+            report::ReportInput::from_json_report(&json, &options, report_path, out_json_path);
+        input.embed = options.embed_images.unwrap_or(false).then(|| embed::EmbedImages {
+            actual_dir: &actual_dir,
+            expected_dir: &expected_dir,
+            diff_dir: &diff_dir,
+            thumbnail_size: options.embed_thumbnail_size,
+        });
+        create_reports(input)?
     };
 
     if let Some(html) = report.html {
-        if let Some(parent) = report_path.parent() {
-            std::fs::create_dir_all(parent).ok();
+        for asset in &report.assets {
+            write_output(Artifact::ReportAsset, &asset.path, &asset.contents)?;
         }
-        std::fs::write(report_path, html)?;
+        write_output(Artifact::Report, report_path, html)?;
         info!(path = %report_path.display(), "Report written (from-json mode)");
     }
 
     if let Some(junit_path) = options.junit_report {
-        if let Some(parent) = junit_path.parent() {
-            std::fs::create_dir_all(parent).ok();
-        }
-        std::fs::write(
+        write_output(
+            Artifact::Junit,
             junit_path,
             report::build_junit_xml(&report.json, options.extended_errors.unwrap_or(false)),
         )?;
     }
 
+    if let Some(dest) = options.bundle {
+        bundle::write(dest, &report.json, &options, &actual_dir, &expected_dir, &diff_dir)?;
+    }
+
     Ok(report.json)
 }
 
+/// Where an image directory listed in the reg.json at `json_path` is on
+/// disk. Directories are stored relative to the file, or as URLs when
+/// `urlPrefix` was set, which only `url_prefix` can trace back.
+fn local_dir(json_path: &Path, dir: &str, url_prefix: Option<&url::Url>) -> Result<PathBuf, CompareError> {
+    let base = json_path.parent().unwrap_or(Path::new(""));
+    // A one-letter scheme is a Windows drive, not a URL.
+    let Some(url) = url::Url::parse(dir).ok().filter(|url| url.scheme().len() > 1) else {
+        return Ok(base.join(dir));
+    };
+    let relative = url_prefix
+        .and_then(|prefix| prefix.make_relative(&url))
+        .ok_or_else(|| CompareError::InvalidOption {
+            option: "url_prefix",
+            reason: format!(
+                "reg.json lists the image directory {dir}; pass the urlPrefix it was written with to embed or bundle its images"
+            ),
+        })?;
+    let relative = urlencoding::decode(&relative)
+        .map(|decoded| decoded.into_owned())
+        .unwrap_or(relative);
+    Ok(base.join(relative))
+}
+
 // Recursively collect supported image files under `root`, returning paths
 // relative to `root` (not absolute).
 //
@@ -619,53 +529,133 @@ pub fn run_from_json(
 // preopen fd directly (verified empirically). So a direct recursive
 // walker starting at `root` sidesteps the trap without needing any
 // sandbox widening.
-fn walk_images(root: &Path) -> BTreeSet<PathBuf> {
-    let mut out: Vec<PathBuf> = Vec::new();
+fn walk_images(root: &Path, opts: WalkOptions<'_>) -> Walk {
+    let mut walk = Walk::default();
     // `root` itself might not exist (e.g. a brand-new actual/ dir that a
     // user forgot to populate). Classic reg-cli treats that as "no images
-    // here" rather than erroring out, so we do too.
-    let Ok(entries) = std::fs::read_dir(root) else {
-        return BTreeSet::new();
+    // here", which makes a typo'd path look like a successful run with
+    // everything new or deleted. Record it and let `find_images` decide.
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) => {
+            walk.unreadable.push((root.to_path_buf(), e));
+            return walk;
+        }
     };
-    // Stack holds (open ReadDir, prefix-relative-to-root). The prefix lets us
-    // emit repo-relative paths without ever allocating an absolute PathBuf for
-    // each file or paying `strip_prefix` per match.
-    let mut stack: Vec<(std::fs::ReadDir, PathBuf)> = vec![(entries, PathBuf::new())];
-    while let Some((dir, prefix)) = stack.last_mut() {
-        match dir.next() {
-            Some(Ok(entry)) => {
-                let Ok(ft) = entry.file_type() else { continue };
-                let name = entry.file_name();
-                if ft.is_dir() {
-                    let sub_path = if prefix.as_os_str().is_empty() {
-                        root.join(&name)
-                    } else {
-                        root.join(&*prefix).join(&name)
-                    };
-                    if let Ok(sub) = std::fs::read_dir(&sub_path) {
-                        let mut sub_prefix = prefix.clone();
-                        sub_prefix.push(&name);
-                        stack.push((sub, sub_prefix));
+    // The stack is the chain of open ancestors (depth-first), each with its
+    // prefix relative to `root`. The prefix lets us emit repo-relative paths
+    // without paying `strip_prefix` per match; the canonical path (only
+    // resolved when following symlinks) is what loop detection checks.
+    struct Frame {
+        dir: std::fs::ReadDir,
+        prefix: PathBuf,
+        depth: usize,
+        canonical: Option<PathBuf>,
+    }
+    let mut stack = vec![Frame {
+        dir: entries,
+        prefix: PathBuf::new(),
+        depth: 0,
+        canonical: opts
+            .follow_symlinks
+            .then(|| std::fs::canonicalize(root).ok())
+            .flatten(),
+    }];
+    while let Some(frame) = stack.last_mut() {
+        let entry = match frame.dir.next() {
+            Some(Ok(entry)) => entry,
+            Some(Err(e)) => {
+                walk.unreadable.push((root.join(&frame.prefix), e));
+                stack.pop();
+                continue;
+            }
+            None => {
+                stack.pop();
+                continue;
+            }
+        };
+        let name = entry.file_name();
+        if opts.skip_hidden && name.as_encoded_bytes().first() == Some(&b'.') {
+            continue;
+        }
+        let rel = frame.prefix.join(&name);
+        let depth = frame.depth + 1;
+
+        let Ok(mut ft) = entry.file_type() else { continue };
+        if ft.is_symlink() {
+            // Classify links by their target; dangling links are skipped.
+            let Ok(meta) = std::fs::metadata(entry.path()) else { continue };
+            ft = meta.file_type();
+            if ft.is_dir() && !opts.follow_symlinks {
+                continue;
+            }
+        }
+
+        if ft.is_dir() {
+            if opts.max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+            let sub_path = root.join(&rel);
+            let canonical = if opts.follow_symlinks {
+                match std::fs::canonicalize(&sub_path) {
+                    Ok(c) if stack.iter().any(|f| f.canonical.as_ref() == Some(&c)) => {
+                        tracing::warn!(path = %sub_path.display(), "Skipping symlink loop");
+                        continue;
+                    }
+                    Ok(c) => Some(c),
+                    Err(e) => {
+                        walk.unreadable.push((sub_path, e));
+                        continue;
                     }
-                } else if is_supported_extension(Path::new(&name)) {
-                    let mut rel = prefix.clone();
-                    rel.push(&name);
-                    out.push(rel);
                 }
+            } else {
+                None
+            };
+            match std::fs::read_dir(&sub_path) {
+                Ok(sub) => stack.push(Frame {
+                    dir: sub,
+                    prefix: rel,
+                    depth,
+                    canonical,
+                }),
+                Err(e) => walk.unreadable.push((sub_path, e)),
             }
-            Some(Err(_)) | None => {
-                stack.pop();
+        } else {
+            let by_extension = extension_format(Path::new(&name));
+            let sniffed = if opts.sniff_content {
+                format::sniff_file(&root.join(&rel))
+            } else {
+                None
+            };
+            // Content wins when it's recognisable; otherwise fall back to
+            // the extension and let a corrupt file fail at decode time.
+            match sniffed.or(by_extension) {
+                Some(f) if opts.formats.contains(&f) => {
+                    if let Some(sniffed) = sniffed.filter(|&s| by_extension != Some(s)) {
+                        if let Some(ext) = rel.extension() {
+                            let ext = ext.to_string_lossy().into_owned();
+                            walk.mismatched.push((rel.clone(), ext, sniffed));
+                        }
+                    }
+                    walk.images.insert(rel);
+                }
+                _ if opts.report_unsupported => {
+                    walk.unsupported.insert(rel);
+                }
+                _ => {}
             }
         }
     }
-    BTreeSet::from_iter(out)
+    walk
 }
 
 #[instrument(fields(expected_dir = %expected_dir.as_ref().display(), actual_dir = %actual_dir.as_ref().display()))]
 pub(crate) fn find_images(
     expected_dir: impl AsRef<Path>,
     actual_dir: impl AsRef<Path>,
-) -> DetectedImages {
+    walk: WalkOptions<'_>,
+    allow_empty_expected: bool,
+) -> Result<DetectedImages, CompareError> {
     let expected_dir = expected_dir.as_ref();
     let actual_dir = actual_dir.as_ref();
 
@@ -673,8 +663,70 @@ pub(crate) fn find_images(
     // uses the global pool — if reg-cli's per-run pool isn't installed yet
     // (we're outside `pool.install`), this falls back to the global pool's
     // workers, which is fine for two tasks.
-    let (expected, actual): (BTreeSet<PathBuf>, BTreeSet<PathBuf>) =
-        rayon::join(|| walk_images(expected_dir), || walk_images(actual_dir));
+    let (expected, actual) = rayon::join(
+        || walk_images(expected_dir, walk),
+        || walk_images(actual_dir, walk),
+    );
+    // A missing expected root is the one "unreadable" case that may be
+    // waved through: first run, no baselines yet.
+    let is_missing_expected_root = |dir: &Path, e: &std::io::Error| {
+        allow_empty_expected && dir == expected_dir && e.kind() == std::io::ErrorKind::NotFound
+    };
+    let mut unreadable = expected
+        .unreadable
+        .into_iter()
+        .filter(|(dir, e)| !is_missing_expected_root(dir, e))
+        .map(|(path, source)| ("expected", path, source))
+        .chain(
+            actual
+                .unreadable
+                .into_iter()
+                .map(|(path, source)| ("actual", path, source)),
+        );
+    if let Some((role, path, source)) = unreadable.next() {
+        return Err(CompareError::Directory { role, path, source });
+    }
+    let unsupported: BTreeSet<PathBuf> = expected
+        .unsupported
+        .union(&actual.unsupported)
+        .cloned()
+        .collect();
+    if !unsupported.is_empty() {
+        tracing::warn!(
+            count = unsupported.len(),
+            "Skipped files with an unsupported extension (see unsupportedItems)"
+        );
+        for path in &unsupported {
+            tracing::warn!(path = %path.display(), "Skipping file with unsupported extension");
+        }
+    }
+    let sides = [
+        ("expected", expected.mismatched),
+        ("actual", actual.mismatched),
+    ];
+    let format_mismatches: BTreeSet<FormatMismatch> = sides
+        .into_iter()
+        .flat_map(|(side, mismatched)| {
+            mismatched
+                .into_iter()
+                .map(move |(path, extension, detected)| FormatMismatch {
+                    side: side.to_string(),
+                    path,
+                    extension,
+                    detected: detected.name().to_string(),
+                })
+        })
+        .collect();
+    for mismatch in &format_mismatches {
+        tracing::warn!(
+            side = %mismatch.side,
+            path = %mismatch.path.display(),
+            extension = %mismatch.extension,
+            detected = %mismatch.detected,
+            "File content doesn't match its extension"
+        );
+    }
+    let (expected, actual) = (expected.images, actual.images);
 
     let deleted: BTreeSet<PathBuf> = expected.difference(&actual).cloned().collect();
     let new: BTreeSet<PathBuf> = actual.difference(&expected).cloned().collect();
@@ -687,12 +739,16 @@ pub(crate) fn find_images(
         "Found images"
     );
 
-    DetectedImages {
+    Ok(DetectedImages {
         expected,
         actual,
         deleted,
         new,
-    }
+        unsupported,
+        format_mismatches,
+        actual_paths: BTreeMap::new(),
+        expected_paths: BTreeMap::new(),
+    })
 }
 
 fn is_passed(
@@ -759,8 +815,8 @@ mod per_image_failure_tests {
 
     /// Smallest possible 1×1 PNG (transparent pixel). Used for the
     /// "valid neighbour" image so we can assert that a corrupt sibling
-    /// doesn't sink the whole batch.
-    const TINY_PNG: &[u8] = &[
+    /// doesn't sink the whole batch. Shared with the comparer tests.
+    pub(crate) const TINY_PNG: &[u8] = &[
         0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
         0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f,
         0x15, 0xc4, 0x89, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00,
@@ -811,7 +867,7 @@ mod per_image_failure_tests {
 /// Non-image extensions (`.txt`, `.md`, etc.) are filtered out by
     /// `find_images` upstream — they should NOT show up in any of the
     /// output buckets. This locks in the "silently skip non-images"
-    /// contract documented in `SUPPORTED_EXTENTIONS`.
+    /// default (`report_unsupported` unset).
     #[test]
     fn non_image_extensions_are_silently_skipped() {
         let tmp = tempfile::tempdir().unwrap();
@@ -840,3 +896,193 @@ mod per_image_failure_tests {
         }
     }
 }
+
+#[cfg(test)]
+mod walk_tests {
+    use super::*;
+    use std::fs;
+
+    fn touch(root: &Path, rel: &str) {
+        let p = root.join(rel);
+        fs::create_dir_all(p.parent().unwrap()).unwrap();
+        fs::write(p, b"").unwrap();
+    }
+
+    fn names(walk: &Walk) -> Vec<String> {
+        walk.images.iter().map(|p| p.display().to_string()).collect()
+    }
+
+    #[test]
+    fn skip_hidden_drops_dot_files_and_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        touch(tmp.path(), "a.png");
+        touch(tmp.path(), ".hidden.png");
+        touch(tmp.path(), ".cache/b.png");
+
+        let all = walk_images(tmp.path(), WalkOptions::default());
+        assert_eq!(all.images.len(), 3);
+
+        let visible = walk_images(
+            tmp.path(),
+            WalkOptions {
+                skip_hidden: true,
+                ..WalkOptions::default()
+            },
+        );
+        assert_eq!(names(&visible), ["a.png"]);
+    }
+
+    #[test]
+    fn max_depth_limits_descent() {
+        let tmp = tempfile::tempdir().unwrap();
+        touch(tmp.path(), "top.png");
+        touch(tmp.path(), "one/mid.png");
+        touch(tmp.path(), "one/two/deep.png");
+
+        let walk = |max_depth| {
+            walk_images(
+                tmp.path(),
+                WalkOptions {
+                    max_depth,
+                    ..WalkOptions::default()
+                },
+            )
+            .images
+            .len()
+        };
+        assert_eq!(walk(Some(0)), 1);
+        assert_eq!(walk(Some(1)), 2);
+        assert_eq!(walk(None), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_dirs_are_followed_only_on_request_and_loops_terminate() {
+        let tmp = tempfile::tempdir().unwrap();
+        touch(tmp.path(), "real/a.png");
+        std::os::unix::fs::symlink(tmp.path().join("real"), tmp.path().join("link")).unwrap();
+        // real/loop -> real: must not recurse forever.
+        std::os::unix::fs::symlink(tmp.path().join("real"), tmp.path().join("real/loop")).unwrap();
+
+        let plain = walk_images(tmp.path(), WalkOptions::default());
+        assert_eq!(names(&plain), ["real/a.png"]);
+
+        let followed = walk_images(
+            tmp.path(),
+            WalkOptions {
+                follow_symlinks: true,
+                ..WalkOptions::default()
+            },
+        );
+        assert_eq!(names(&followed), ["link/a.png", "real/a.png"]);
+        assert!(followed.unreadable.is_empty());
+    }
+
+    #[test]
+    fn formats_are_opt_in_and_skipped_files_can_be_reported() {
+        let tmp = tempfile::tempdir().unwrap();
+        touch(tmp.path(), "a.png");
+        touch(tmp.path(), "icon.SVG");
+        touch(tmp.path(), "notes.txt");
+
+        let classic = walk_images(tmp.path(), WalkOptions::default());
+        assert_eq!(names(&classic), ["a.png"]);
+        assert!(classic.unsupported.is_empty());
+
+        let with_svg = walk_images(
+            tmp.path(),
+            WalkOptions {
+                formats: &[ImageFormat::Png, ImageFormat::Svg],
+                report_unsupported: true,
+                ..WalkOptions::default()
+            },
+        );
+        assert_eq!(names(&with_svg), ["a.png", "icon.SVG"]);
+        assert_eq!(
+            with_svg.unsupported.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("notes.txt")]
+        );
+    }
+
+    #[test]
+    fn sniffing_classifies_by_content_and_flags_mismatches() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("capture"), b"\x89PNG\r\n\x1a\n").unwrap();
+        fs::write(tmp.path().join("photo.png"), [0xff, 0xd8, 0xff, 0xe0]).unwrap();
+        fs::write(tmp.path().join("fake.png.txt"), b"\x89PNG\r\n\x1a\n").unwrap();
+        fs::write(tmp.path().join("notes.png"), b"not an image").unwrap();
+
+        let by_extension = walk_images(tmp.path(), WalkOptions::default());
+        assert_eq!(names(&by_extension), ["notes.png", "photo.png"]);
+
+        let sniffed = walk_images(
+            tmp.path(),
+            WalkOptions {
+                sniff_content: true,
+                ..WalkOptions::default()
+            },
+        );
+        // Unrecognisable content falls back to the extension.
+        assert_eq!(
+            names(&sniffed),
+            ["capture", "fake.png.txt", "notes.png", "photo.png"]
+        );
+        let mut mismatched: Vec<_> = sniffed
+            .mismatched
+            .iter()
+            .map(|(p, ext, f)| (p.display().to_string(), ext.as_str(), *f))
+            .collect();
+        mismatched.sort();
+        assert_eq!(
+            mismatched,
+            [
+                ("fake.png.txt".to_string(), "txt", ImageFormat::Png),
+                ("photo.png".to_string(), "png", ImageFormat::Jpeg),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_roots_are_errors_but_empty_roots_are_not() {
+        let tmp = tempfile::tempdir().unwrap();
+        let actual = tmp.path().join("actual");
+        let expected = tmp.path().join("expected");
+        touch(&actual, "a.png");
+        let find = |allow_empty_expected| {
+            find_images(&expected, &actual, WalkOptions::default(), allow_empty_expected)
+        };
+
+        assert!(matches!(
+            find(false),
+            Err(CompareError::Directory { role: "expected", .. })
+        ));
+        let first_run = find(true).unwrap();
+        assert_eq!(first_run.new.len(), 1);
+
+        fs::create_dir_all(&expected).unwrap();
+        assert_eq!(find(false).unwrap().new.len(), 1);
+
+        assert!(matches!(
+            find_images(&expected, tmp.path().join("typo"), WalkOptions::default(), true),
+            Err(CompareError::Directory { role: "actual", .. })
+        ));
+    }
+}
+
+#[cfg(test)]
+mod from_json_tests {
+    use super::*;
+
+    #[test]
+    fn url_prefixed_dirs_resolve_against_the_prefix() {
+        let json = Path::new("out/reg.json");
+        let prefix = url::Url::parse("https://example.com/run/42/").unwrap();
+        assert_eq!(
+            local_dir(json, "https://example.com/run/42/my%20actual", Some(&prefix)).unwrap(),
+            Path::new("out/my actual")
+        );
+        assert_eq!(local_dir(json, "../diff", None).unwrap(), Path::new("out/../diff"));
+        let err = local_dir(json, "https://example.com/run/42/actual", None).unwrap_err();
+        assert_eq!(err.code(), "INVALID_OPTION");
+    }
+}
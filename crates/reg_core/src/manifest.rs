@@ -0,0 +1,347 @@
+//! Explicit actual/expected pairings.
+//!
+//! `find_images` pairs files by identical relative path, which doesn't fit
+//! pipelines that store baselines under a different layout (e.g.
+//! `chrome/home.png` vs `baseline/home@chrome.png`). A manifest lists each
+//! pair under a logical name instead; that name is what shows up in reg.json,
+//! progress events, JUnit and the diff image path.
+//!
+//! Two on-disk formats are accepted, picked by extension:
+//!
+//! ```json
+//! [{ "name": "home.png", "actual": "chrome/home.png", "expected": "baseline/home@chrome.png" }]
+//! ```
+//!
+//! ```csv
+//! name,actual,expected
+//! home.png,chrome/home.png,baseline/home@chrome.png
+//! ```
+//!
+//! Relative `actual` / `expected` paths resolve against `actual_dir` /
+//! `expected_dir`. reg.json records them as `actualPaths` / `expectedPaths`
+//! wherever they differ from the name, and the HTML report loads previews
+//! from there.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{comparer::ImagePair, CompareError, DetectedImages};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ManifestEntry {
+    pub name: PathBuf,
+    pub actual: PathBuf,
+    pub expected: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Load a manifest from `path`; `.csv` files are parsed as CSV, anything
+    /// else as a JSON array.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CompareError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|source| CompareError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let is_csv = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+        let entries = if is_csv {
+            parse_csv(&content)
+        } else {
+            serde_json::from_str(&content).map_err(|e| e.to_string())
+        }
+        .map_err(|reason| manifest_error(path, reason))?;
+        let manifest = Manifest { entries };
+        manifest.validate().map_err(|reason| manifest_error(path, reason))?;
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut seen = BTreeSet::new();
+        for e in &self.entries {
+            if e.name.as_os_str().is_empty() {
+                return Err("entry with empty name".to_string());
+            }
+            // Names become paths under the diff and thumbnail dirs.
+            if !e.name.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(format!(
+                    "name {} must be a relative path without `..`",
+                    e.name.display()
+                ));
+            }
+            if !seen.insert(&e.name) {
+                return Err(format!("duplicate name {}", e.name.display()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Classify entries the same way `find_images` classifies a directory
+    /// walk: present on both sides → pair to diff, actual only → new,
+    /// expected only → deleted. An entry missing on both sides is almost
+    /// certainly a typo in the manifest, so it's an error rather than a
+    /// silent drop. `path` is the manifest file, for errors.
+    pub(crate) fn detect(
+        &self,
+        path: &Path,
+        actual_dir: &Path,
+        expected_dir: &Path,
+    ) -> Result<(DetectedImages, Vec<ImagePair>), CompareError> {
+        let mut actual = BTreeSet::new();
+        let mut expected = BTreeSet::new();
+        let mut actual_paths = BTreeMap::new();
+        let mut expected_paths = BTreeMap::new();
+        let mut pairs = Vec::new();
+        for e in &self.entries {
+            let actual_path = actual_dir.join(&e.actual);
+            let expected_path = expected_dir.join(&e.expected);
+            let has_actual = actual_path.is_file();
+            let has_expected = expected_path.is_file();
+            if !has_actual && !has_expected {
+                return Err(manifest_error(
+                    path,
+                    format!(
+                        "entry {}: neither {} nor {} exists",
+                        e.name.display(),
+                        actual_path.display(),
+                        expected_path.display()
+                    ),
+                ));
+            }
+            if has_actual {
+                actual.insert(e.name.clone());
+                if e.actual != e.name {
+                    actual_paths.insert(e.name.clone(), e.actual.clone());
+                }
+            }
+            if has_expected {
+                expected.insert(e.name.clone());
+                if e.expected != e.name {
+                    expected_paths.insert(e.name.clone(), e.expected.clone());
+                }
+            }
+            if has_actual && has_expected {
+                pairs.push(ImagePair {
+                    name: e.name.clone(),
+                    actual: actual_path,
+                    expected: expected_path,
+                });
+            }
+        }
+        let deleted = expected.difference(&actual).cloned().collect();
+        let new = actual.difference(&expected).cloned().collect();
+        Ok((
+            DetectedImages {
+                expected,
+                actual,
+                deleted,
+                new,
+                unsupported: BTreeSet::new(),
+                format_mismatches: BTreeSet::new(),
+                actual_paths,
+                expected_paths,
+            },
+            pairs,
+        ))
+    }
+}
+
+fn manifest_error(path: &Path, reason: String) -> CompareError {
+    CompareError::Manifest {
+        path: path.to_path_buf(),
+        reason,
+    }
+}
+
+/// Minimal RFC 4180 reader: a `name,actual,expected` header (any column
+/// order), comma separators, and `"..."` quoting with `""` escapes. Blank
+/// lines are skipped. Quoted fields may not span lines.
+fn parse_csv(content: &str) -> Result<Vec<ManifestEntry>, String> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let header = split_csv_line(header).map_err(|e| format!("line 1: {e}"))?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| format!("missing `{name}` column"))
+    };
+    let (name_col, actual_col, expected_col) = (column("name")?, column("actual")?, column("expected")?);
+
+    lines
+        .map(|(i, line)| {
+            let fields = split_csv_line(line).map_err(|e| format!("line {}: {e}", i + 1))?;
+            let field = |col: usize| {
+                fields
+                    .get(col)
+                    .map(PathBuf::from)
+                    .ok_or_else(|| format!("line {}: expected {} fields", i + 1, header.len()))
+            };
+            Ok(ManifestEntry {
+                name: field(name_col)?,
+                actual: field(actual_col)?,
+                expected: field(expected_col)?,
+            })
+        })
+        .collect()
+}
+
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut cur = String::new();
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                cur.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if cur.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut cur)),
+            (_, c) => cur.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(cur);
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn entry(name: &str, actual: &str, expected: &str) -> ManifestEntry {
+        ManifestEntry {
+            name: name.into(),
+            actual: actual.into(),
+            expected: expected.into(),
+        }
+    }
+
+    #[test]
+    fn csv_with_reordered_columns_and_quotes() {
+        let csv = "expected,name,actual\r\n\"baseline/a,b.png\",a.png,chrome/a.png\r\n\nx\"\"y.png,\"q\"\"uote.png\",z.png\n";
+        assert_eq!(
+            parse_csv(csv).unwrap(),
+            vec![
+                entry("a.png", "chrome/a.png", "baseline/a,b.png"),
+                entry("q\"uote.png", "z.png", "x\"\"y.png"),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_missing_column_is_an_error() {
+        assert!(parse_csv("name,actual\na,b\n").unwrap_err().contains("expected"));
+    }
+
+    #[test]
+    fn load_rejects_duplicate_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("m.json");
+        fs::write(
+            &path,
+            r#"[{"name":"a.png","actual":"1.png","expected":"2.png"},
+                {"name":"a.png","actual":"3.png","expected":"4.png"}]"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Manifest::load(&path),
+            Err(CompareError::Manifest { .. })
+        ));
+    }
+
+    #[test]
+    fn load_rejects_names_outside_the_output_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("m.json");
+        for name in ["../x.png", "/abs.png"] {
+            fs::write(
+                &path,
+                format!(r#"[{{"name":"{name}","actual":"1.png","expected":"2.png"}}]"#),
+            )
+            .unwrap();
+            let err = Manifest::load(&path).unwrap_err();
+            assert!(matches!(err, CompareError::Manifest { .. }), "{name}");
+            assert!(err.to_string().contains(name));
+        }
+    }
+
+    #[test]
+    fn detect_classifies_by_presence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let actual = tmp.path().join("chrome");
+        let expected = tmp.path().join("baseline");
+        fs::create_dir_all(&actual).unwrap();
+        fs::create_dir_all(&expected).unwrap();
+        fs::write(actual.join("home.png"), b"").unwrap();
+        fs::write(expected.join("home@chrome.png"), b"").unwrap();
+        fs::write(actual.join("added.png"), b"").unwrap();
+        fs::write(expected.join("gone@chrome.png"), b"").unwrap();
+
+        let manifest = Manifest {
+            entries: vec![
+                entry("home.png", "home.png", "home@chrome.png"),
+                entry("added.png", "added.png", "added@chrome.png"),
+                entry("gone.png", "gone.png", "gone@chrome.png"),
+            ],
+        };
+        let (detected, pairs) = manifest
+            .detect(Path::new("pairs.csv"), &actual, &expected)
+            .unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].expected, expected.join("home@chrome.png"));
+        assert!(detected.new.contains(Path::new("added.png")));
+        assert!(detected.deleted.contains(Path::new("gone.png")));
+        assert!(detected.actual_paths.is_empty());
+        assert_eq!(
+            detected.expected_paths,
+            BTreeMap::from([
+                ("home.png".into(), "home@chrome.png".into()),
+                ("gone.png".into(), "gone@chrome.png".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn load_reports_the_missing_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("missing.csv");
+        let err = Manifest::load(&path).unwrap_err();
+        assert!(matches!(err, CompareError::Read { .. }));
+        assert_eq!(err.path(), Some(path.as_path()));
+    }
+
+    #[test]
+    fn detect_errors_when_both_sides_missing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manifest = Manifest {
+            entries: vec![entry("typo.png", "typo.png", "typo.png")],
+        };
+        let path = tmp.path().join("pairs.csv");
+        let err = manifest.detect(&path, tmp.path(), tmp.path()).unwrap_err();
+        assert_eq!(err.path(), Some(path.as_path()));
+        assert!(err.to_string().contains("entry typo.png: neither"));
+    }
+}
@@ -0,0 +1,311 @@
+//! Path rewrite rules between the actual and expected trees.
+//!
+//! By default an actual image pairs with the expected image at the same
+//! relative path. A `PathMapping` rewrites the actual-side path first, so
+//! e.g. `^(.*)\.png$` → `$1@chrome.png` pairs `home.png` with
+//! `home@chrome.png`. Results are still reported under the actual-side
+//! name, with the expected file recorded in `expectedPaths`; expected images
+//! nothing maps onto are reported as deleted. Rules mapping two actual
+//! images onto one expected image are rejected.
+//!
+//! With case-insensitive matching, the lookup compares folded keys
+//! (NFC-normalised, lowercased) instead, so `Home.png` written on macOS
+//! pairs with `home.png` from Linux.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{comparer::ImagePair, CompareError, DetectedImages};
+
+/// Separator between pattern and replacement in [`PathMapping::parse`].
+const RULE_SEPARATOR: &str = "=>";
+
+/// One regex rewrite from an actual-relative path to an expected-relative
+/// path. Paths are matched with `/` separators on every platform.
+#[derive(Debug, Clone)]
+pub struct PathMapping {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl PathMapping {
+    /// `replacement` uses the `regex` crate's syntax (`$1`, `${name}`).
+    pub fn new(pattern: &str, replacement: impl Into<String>) -> Result<Self, CompareError> {
+        let compiled = Regex::new(pattern).map_err(|e| CompareError::InvalidPathMapping {
+            rule: pattern.to_string(),
+            reason: e.to_string(),
+        })?;
+        Ok(Self {
+            pattern: compiled,
+            replacement: replacement.into(),
+        })
+    }
+
+    /// Parse the CLI form `pattern=>replacement`.
+    pub fn parse(rule: &str) -> Result<Self, CompareError> {
+        let (pattern, replacement) =
+            rule.split_once(RULE_SEPARATOR)
+                .ok_or_else(|| CompareError::InvalidPathMapping {
+                    rule: rule.to_string(),
+                    reason: format!("expected `pattern{RULE_SEPARATOR}replacement`"),
+                })?;
+        Self::new(pattern, replacement)
+    }
+
+    /// Rewritten path, or `None` when the pattern doesn't match.
+    pub fn apply(&self, path: &Path) -> Option<PathBuf> {
+        let key = slash_path(path);
+        if !self.pattern.is_match(&key) {
+            return None;
+        }
+        let rewritten = self.pattern.replace(&key, self.replacement.as_str());
+        Some(native_path(&rewritten))
+    }
+
+    /// The rule in its CLI form, for error messages.
+    fn rule(&self) -> String {
+        format!("{}{RULE_SEPARATOR}{}", self.pattern.as_str(), self.replacement)
+    }
+}
+
+fn slash_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+fn native_path(slashed: &str) -> PathBuf {
+    PathBuf::from(slashed.replace('/', std::path::MAIN_SEPARATOR_STR))
+}
+
+/// Expected-side path for `actual`: the first matching rule wins, no match
+/// keeps the path unchanged.
+pub(crate) fn map_path(rules: &[PathMapping], actual: &Path) -> PathBuf {
+    rules
+        .iter()
+        .find_map(|r| r.apply(actual))
+        .unwrap_or_else(|| actual.to_path_buf())
+}
+
+/// Lookup key for `path`: unchanged, or NFC-normalised and lowercased when
+/// `fold` is set. Folding works on the `/`-separated form so keys compare
+/// equal across platforms.
+fn match_key(path: &Path, fold: bool) -> PathBuf {
+    if !fold {
+        return path.to_path_buf();
+    }
+    let folded: String = slash_path(path).nfc().flat_map(char::to_lowercase).collect();
+    native_path(&folded)
+}
+
+/// Index `paths` by [`match_key`]. Two paths folding to the same key can't
+/// be paired unambiguously, so that's an error rather than a guess.
+fn index_by_key(
+    paths: &BTreeSet<PathBuf>,
+    fold: bool,
+) -> Result<BTreeMap<PathBuf, &PathBuf>, CompareError> {
+    let mut index = BTreeMap::new();
+    for path in paths {
+        if let Some(first) = index.insert(match_key(path, fold), path) {
+            return Err(CompareError::PathCollision {
+                first: first.clone(),
+                second: path.clone(),
+            });
+        }
+    }
+    Ok(index)
+}
+
+/// Re-pair a directory walk through `rules`, optionally matching folded
+/// paths (see [`match_key`]). `walked.new` / `walked.deleted` are
+/// recomputed; `actual` / `expected` inventories are left as walked.
+pub(crate) fn pair_walked(
+    walked: DetectedImages,
+    rules: &[PathMapping],
+    fold: bool,
+    actual_dir: &Path,
+    expected_dir: &Path,
+) -> Result<(DetectedImages, Vec<ImagePair>), CompareError> {
+    let expected = index_by_key(&walked.expected, fold)?;
+    // Only checked for collisions: two actual files folding together would
+    // both claim the same baseline.
+    index_by_key(&walked.actual, fold)?;
+
+    let mut pairs = Vec::new();
+    let mut new = BTreeSet::new();
+    // Expected image → the actual image paired with it.
+    let mut matched: BTreeMap<&PathBuf, &PathBuf> = BTreeMap::new();
+    let mut expected_paths = BTreeMap::new();
+    for name in &walked.actual {
+        let target = map_path(rules, name);
+        match expected.get(&match_key(&target, fold)) {
+            Some(&target) => {
+                if let Some(first) = matched.insert(target, name) {
+                    return Err(collision(rules, first, name, target));
+                }
+                pairs.push(ImagePair {
+                    name: name.clone(),
+                    actual: actual_dir.join(name),
+                    expected: expected_dir.join(target),
+                });
+                if target != name {
+                    expected_paths.insert(name.clone(), target.clone());
+                }
+            }
+            None => {
+                new.insert(name.clone());
+            }
+        }
+    }
+    let deleted = walked
+        .expected
+        .iter()
+        .filter(|path| !matched.contains_key(path))
+        .cloned()
+        .collect();
+    Ok((
+        DetectedImages {
+            new,
+            deleted,
+            expected_paths,
+            ..walked
+        },
+        pairs,
+    ))
+}
+
+/// `first` and `second` both pair with the expected image `target`. Blame
+/// the rule that mapped `second` there, or failing that the one that
+/// mapped `first`.
+fn collision(rules: &[PathMapping], first: &Path, second: &Path, target: &Path) -> CompareError {
+    let rule = [second, first]
+        .into_iter()
+        .find_map(|path| rules.iter().find(|r| r.apply(path).is_some()))
+        .map(PathMapping::rule)
+        .unwrap_or_default();
+    CompareError::InvalidPathMapping {
+        rule,
+        reason: format!(
+            "{} and {} both map to {}",
+            first.display(),
+            second.display(),
+            target.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(items: &[&str]) -> BTreeSet<PathBuf> {
+        items.iter().map(PathBuf::from).collect()
+    }
+
+    /// What a walk finding `actual` and `expected` hands to `pair_walked`.
+    fn detected(actual: &[&str], expected: &[&str]) -> DetectedImages {
+        DetectedImages {
+            actual: set(actual),
+            expected: set(expected),
+            new: BTreeSet::new(),
+            deleted: BTreeSet::new(),
+            unsupported: BTreeSet::new(),
+            format_mismatches: BTreeSet::new(),
+            actual_paths: BTreeMap::new(),
+            expected_paths: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn parse_and_apply_with_capture_groups() {
+        let rule = PathMapping::parse(r"^chrome/(.*)\.png$=>baseline/$1@chrome.png").unwrap();
+        assert_eq!(
+            rule.apply(Path::new("chrome/home.png")),
+            Some(PathBuf::from("baseline/home@chrome.png"))
+        );
+        assert_eq!(rule.apply(Path::new("firefox/home.png")), None);
+    }
+
+    #[test]
+    fn parse_rejects_missing_separator_and_bad_regex() {
+        assert!(matches!(
+            PathMapping::parse("no-arrow"),
+            Err(CompareError::InvalidPathMapping { .. })
+        ));
+        assert!(matches!(
+            PathMapping::parse("(unclosed=>x"),
+            Err(CompareError::InvalidPathMapping { .. })
+        ));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            PathMapping::new(r"^a/", "first/").unwrap(),
+            PathMapping::new(r"^a/", "second/").unwrap(),
+        ];
+        assert_eq!(map_path(&rules, Path::new("a/x.png")), Path::new("first/x.png"));
+        assert_eq!(map_path(&rules, Path::new("b/x.png")), Path::new("b/x.png"));
+    }
+
+    #[test]
+    fn pair_walked_uses_mapped_expected_paths() {
+        let walked = detected(&["home.png", "added.png"], &["home@chrome.png", "stale@chrome.png"]);
+        let rules = [PathMapping::new(r"^(.*)\.png$", "$1@chrome.png").unwrap()];
+        let (detected, pairs) =
+            pair_walked(walked, &rules, false, Path::new("actual"), Path::new("expected"))
+                .unwrap();
+
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].name, Path::new("home.png"));
+        assert_eq!(pairs[0].expected, Path::new("expected/home@chrome.png"));
+        assert_eq!(detected.new, set(&["added.png"]));
+        assert_eq!(detected.deleted, set(&["stale@chrome.png"]));
+        assert_eq!(
+            detected.expected_paths,
+            BTreeMap::from([("home.png".into(), "home@chrome.png".into())])
+        );
+    }
+
+    #[test]
+    fn two_actual_images_mapped_onto_one_expected_image_are_rejected() {
+        let walked = detected(&["a/home.png", "b/home.png"], &["home.png"]);
+        let rules = [PathMapping::parse(r"^[ab]/=>").unwrap()];
+        let err = pair_walked(walked, &rules, false, Path::new("a"), Path::new("e")).unwrap_err();
+        assert!(matches!(
+            &err,
+            CompareError::InvalidPathMapping { rule, .. } if rule == "^[ab]/=>"
+        ));
+    }
+
+    #[test]
+    fn folded_matching_ignores_case_and_normalisation() {
+        // "Café" in NFD (e + combining acute) on the actual side, NFC and
+        // lowercase on the expected side.
+        let walked = || detected(&["Cafe\u{301}/Home.PNG", "other.png"], &["caf\u{e9}/home.png"]);
+        let (detected, pairs) =
+            pair_walked(walked(), &[], true, Path::new("a"), Path::new("e")).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].expected, Path::new("e/caf\u{e9}/home.png"));
+        assert_eq!(detected.new, set(&["other.png"]));
+        assert!(detected.deleted.is_empty());
+
+        let (exact, pairs) =
+            pair_walked(walked(), &[], false, Path::new("a"), Path::new("e")).unwrap();
+        assert!(pairs.is_empty());
+        assert_eq!(exact.deleted.len(), 1);
+    }
+
+    #[test]
+    fn folded_collisions_are_errors() {
+        let walked = detected(&["a.png"], &["Shot.png", "shot.png"]);
+        assert!(matches!(
+            pair_walked(walked, &[], true, Path::new("a"), Path::new("e")),
+            Err(CompareError::PathCollision { .. })
+        ));
+    }
+}
@@ -0,0 +1,435 @@
+//! Writing artefacts so readers never see them half-done.
+//!
+//! Every output (reg.json, the HTML report, JUnit XML, diff images) goes
+//! through [`write_output`]: the parent directory is created, then the bytes
+//! land in a temporary file next to the target and are renamed over it, so a
+//! crash or a concurrent reader sees either the old file or the new one.
+//! Failures come back as [`CompareError::Write`] naming the artefact.
+//! [`OutputLock`] keeps two runs from writing the same outputs at once.
+
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    fmt,
+    fs::{self, OpenOptions, TryLockError},
+    hash::{BuildHasher, Hasher},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use once_cell::sync::Lazy;
+
+use crate::CompareError;
+
+/// Suffix of the lock file taken next to each output for the duration of a
+/// run: `diff/` is guarded by `.diff.reg-cli.lock`, `reg.json` by
+/// `.reg.json.reg-cli.lock`.
+pub(crate) const LOCK_SUFFIX: &str = ".reg-cli.lock";
+
+/// What a write was for, so errors can say which output failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    Json,
+    Report,
+    ReportAsset,
+    Junit,
+    DiffImage,
+    DiffManifest,
+    Lock,
+    Bundle,
+    Thumbnail,
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Artifact::Json => "reg.json",
+            Artifact::Report => "HTML report",
+            Artifact::ReportAsset => "report asset",
+            Artifact::Junit => "JUnit XML",
+            Artifact::DiffImage => "diff image",
+            Artifact::DiffManifest => "diff manifest",
+            Artifact::Lock => "lock file",
+            Artifact::Bundle => "report bundle",
+            Artifact::Thumbnail => "thumbnail",
+        })
+    }
+}
+
+/// Create `path`'s parent directory and atomically write `contents` there.
+///
+/// Diff images are left to the page cache; the comparer flushes them in one
+/// pass with [`sync_outputs`] once every diff is written. Everything else is
+/// flushed before the rename.
+pub fn write_output(
+    artifact: Artifact,
+    path: impl AsRef<Path>,
+    contents: impl AsRef<[u8]>,
+) -> Result<(), CompareError> {
+    let path = path.as_ref();
+    let error = |source| CompareError::Write {
+        artifact,
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(error)?;
+    }
+    write_atomic(path, contents, artifact != Artifact::DiffImage).map_err(error)
+}
+
+/// Flush `files` under `dir`, then `dir` itself, so a batch written with
+/// [`write_output`] survives a crash.
+pub(crate) fn sync_outputs<'p>(
+    artifact: Artifact,
+    dir: &Path,
+    files: impl IntoIterator<Item = &'p PathBuf>,
+) -> Result<(), CompareError> {
+    let sync = |path: PathBuf| {
+        fs::File::open(&path)
+            .and_then(|file| file.sync_all())
+            .map_err(|source| CompareError::Write {
+                artifact,
+                path,
+                source,
+            })
+    };
+    for file in files {
+        sync(dir.join(file))?;
+    }
+    // Directories can only be opened for syncing on Unix; elsewhere the
+    // renames are as durable as the platform makes them.
+    if cfg!(unix) && dir.is_dir() {
+        sync(dir.to_path_buf())?;
+    }
+    Ok(())
+}
+
+/// Write `contents` to `path` via a temporary file in the same directory
+/// and a rename, flushing the file first when `sync` is set. The parent
+/// directory must exist.
+fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>, sync: bool) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = tmp_path(path)?;
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        file.write_all(contents.as_ref())?;
+        if sync {
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// `.<name>.<nonce>.<n>.tmp` beside `path`.
+fn tmp_path(path: &Path) -> io::Result<PathBuf> {
+    // Unique per process and call, so parallel diff writers and concurrent
+    // runs never share a temporary file. Random rather than the PID, which
+    // WASI doesn't provide.
+    static NONCE: Lazy<u64> = Lazy::new(|| RandomState::new().build_hasher().finish());
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        )
+    })?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(
+        ".{:016x}.{}.tmp",
+        *NONCE,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// An output too large to hold in memory, written piece by piece. The bytes
+/// go to a temporary file beside the target, which
+/// [`commit`](Self::commit) flushes and renames over it; dropped without
+/// committing, the temporary file is removed.
+#[derive(Debug)]
+pub(crate) struct PendingOutput {
+    artifact: Artifact,
+    path: PathBuf,
+    tmp: PathBuf,
+    file: io::BufWriter<fs::File>,
+    committed: bool,
+}
+
+impl PendingOutput {
+    /// Create `path`'s parent directory and start writing its replacement.
+    pub(crate) fn create(artifact: Artifact, path: &Path) -> Result<Self, CompareError> {
+        let error = |source| CompareError::Write {
+            artifact,
+            path: path.to_path_buf(),
+            source,
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(error)?;
+        }
+        let tmp = tmp_path(path).map_err(error)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+            .map_err(error)?;
+        Ok(Self {
+            artifact,
+            path: path.to_path_buf(),
+            tmp,
+            file: io::BufWriter::new(file),
+            committed: false,
+        })
+    }
+
+    /// Flush everything written and move it into place.
+    pub(crate) fn commit(mut self) -> Result<(), CompareError> {
+        let result = self
+            .file
+            .flush()
+            .and_then(|()| self.file.get_ref().sync_all())
+            .and_then(|()| fs::rename(&self.tmp, &self.path));
+        self.committed = result.is_ok();
+        result.map_err(|source| CompareError::Write {
+            artifact: self.artifact,
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+impl Write for PendingOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for PendingOutput {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for PendingOutput {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Exclusive claim on a run's outputs, released on drop.
+///
+/// Each output (the diff dir, reg.json, the report, ...) gets a lock file
+/// beside it holding an OS advisory lock, so the claim goes away with the
+/// process: a run that is killed outright leaves at most an unlocked file,
+/// which the next run simply takes over. Only the parent directories are
+/// created. Claims are also tracked per process, which is all that guards
+/// against concurrent runs under WASI, where file locks are unsupported.
+#[derive(Debug)]
+pub(crate) struct OutputLock {
+    held: Vec<HeldLock>,
+}
+
+#[derive(Debug)]
+struct HeldLock {
+    path: PathBuf,
+    /// Keeps the advisory lock; `None` when the platform has none.
+    _file: Option<fs::File>,
+}
+
+/// Lock files claimed by this process.
+static CLAIMED: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(Default::default);
+
+impl OutputLock {
+    /// Lock every path in `outputs`, failing with
+    /// [`CompareError::OutputLocked`] when another run holds any of them.
+    pub(crate) fn acquire<'p>(outputs: impl IntoIterator<Item = &'p Path>) -> Result<Self, CompareError> {
+        let mut lock = Self { held: Vec::new() };
+        let mut paths: Vec<PathBuf> = outputs.into_iter().filter_map(lock_path).collect();
+        // A fixed order, so two runs sharing several outputs can't each hold
+        // half of them.
+        paths.sort();
+        paths.dedup();
+        for path in paths {
+            lock.held.push(HeldLock::acquire(path)?);
+        }
+        Ok(lock)
+    }
+}
+
+/// `<parent>/.<name>.reg-cli.lock` for an output at `<parent>/<name>`.
+fn lock_path(output: &Path) -> Option<PathBuf> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(output.file_name()?);
+    name.push(LOCK_SUFFIX);
+    Some(output.with_file_name(name))
+}
+
+impl HeldLock {
+    fn acquire(path: PathBuf) -> Result<Self, CompareError> {
+        let error = |path: &Path, source| CompareError::Write {
+            artifact: Artifact::Lock,
+            path: path.to_path_buf(),
+            source,
+        };
+        let locked = |path: PathBuf| {
+            let holder = fs::read_to_string(&path).unwrap_or_default();
+            CompareError::OutputLocked {
+                path,
+                holder: holder.trim().to_string(),
+            }
+        };
+
+        if !CLAIMED.lock().unwrap().insert(path.clone()) {
+            return Err(locked(path));
+        }
+        // From here on, dropping `held` releases the claim.
+        let mut held = Self { path, _file: None };
+        if let Some(parent) = held.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| error(&held.path, e))?;
+        }
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&held.path)
+                .map_err(|e| error(&held.path, e))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => return Err(locked(held.path.clone())),
+                Err(TryLockError::Error(e)) if e.kind() == io::ErrorKind::Unsupported => {
+                    return Ok(held);
+                }
+                Err(TryLockError::Error(e)) => return Err(error(&held.path, e)),
+            }
+            // The previous holder may have removed the file between our open
+            // and lock; then the lock we hold is on an orphan, so start over.
+            if !is_same_file(&file, &held.path) {
+                continue;
+            }
+            // Informational only: shown to a run that finds the lock taken.
+            let _ = file.set_len(0);
+            let _ = writeln!(file, "{}", holder_description());
+            held._file = Some(file);
+            return Ok(held);
+        }
+    }
+}
+
+impl Drop for HeldLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so the next run either sees the file
+        // gone or finds it was orphaned (see `is_same_file`).
+        if self._file.is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+        CLAIMED.lock().unwrap().remove(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &fs::File, path: &Path) -> bool {
+    path.exists()
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn holder_description() -> String {
+    format!("pid {}", std::process::id())
+}
+
+#[cfg(target_os = "wasi")]
+fn holder_description() -> String {
+    "a WASI process".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomic_replaces_and_leaves_no_temp_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("reg.json");
+        write_atomic(&target, "old", true).unwrap();
+        write_atomic(&target, "new", false).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_output_creates_parents_and_names_the_artifact() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("a/b/junit.xml");
+        write_output(Artifact::Junit, &target, "<xml/>").unwrap();
+        assert!(target.is_file());
+
+        // A file where a directory is needed.
+        let blocked = target.join("nested.xml");
+        let err = write_output(Artifact::Junit, &blocked, "<xml/>").unwrap_err();
+        assert!(matches!(
+            err,
+            CompareError::Write {
+                artifact: Artifact::Junit,
+                ..
+            }
+        ));
+        assert!(err.to_string().starts_with("cannot write JUnit XML to "));
+    }
+
+    #[test]
+    fn second_lock_fails_until_first_is_dropped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("diff");
+        let json = tmp.path().join("reg.json");
+        let first = OutputLock::acquire([dir.as_path(), json.as_path()]).unwrap();
+        // Locking never creates the outputs themselves.
+        assert!(!dir.exists());
+        let err = OutputLock::acquire([json.as_path()]).unwrap_err();
+        assert!(matches!(err, CompareError::OutputLocked { .. }));
+        assert!(err.to_string().contains("pid "));
+        drop(first);
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+        OutputLock::acquire([dir.as_path()]).unwrap();
+    }
+
+    #[test]
+    fn leftover_lock_file_from_a_dead_run_is_taken_over() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("diff");
+        let leftover = lock_path(&dir).unwrap();
+        fs::write(&leftover, "pid 1").unwrap();
+        let lock = OutputLock::acquire([dir.as_path()]).unwrap();
+        assert_eq!(
+            fs::read_to_string(&leftover).unwrap().trim(),
+            holder_description()
+        );
+        drop(lock);
+        assert!(!leftover.exists());
+    }
+}
@@ -0,0 +1,155 @@
+//! Pruning diff images left over from earlier runs.
+//!
+//! With `Options::clean_diff_dir`, a run records the diff images it wrote
+//! in [`MANIFEST_NAME`] inside the diff dir and removes what the previous
+//! run's list names: everything before comparing, or after comparing only
+//! the files it didn't write again. Nothing else in the directory is
+//! touched, so files reg-cli didn't record (including those of runs without
+//! the option) stay.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{write_output, Artifact, CompareError};
+
+/// Written next to the diff images. The leading dot keeps it out of most
+/// upload globs.
+pub(crate) const MANIFEST_NAME: &str = ".reg-cli-diffs.json";
+
+/// When stale diff images are removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CleanDiffDir {
+    /// Remove every tracked diff image before comparing.
+    Before,
+    /// After comparing, remove tracked diff images this run didn't rewrite.
+    /// Images that are still current are never missing mid-run.
+    After,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DiffManifest {
+    /// Paths relative to the diff dir.
+    files: BTreeSet<PathBuf>,
+}
+
+/// The diff images the previous run recorded. A missing manifest is an
+/// empty list; an unreadable one is warned about and treated the same, as
+/// there's no way to know what it covered.
+pub(crate) fn load(diff_dir: &Path) -> BTreeSet<PathBuf> {
+    let path = diff_dir.join(MANIFEST_NAME);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return BTreeSet::new(),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "cannot read diff manifest");
+            return BTreeSet::new();
+        }
+    };
+    match serde_json::from_str::<DiffManifest>(&content) {
+        Ok(manifest) => manifest.files,
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "ignoring malformed diff manifest");
+            BTreeSet::new()
+        }
+    }
+}
+
+/// Delete `files` from `diff_dir`, then any directories that became empty
+/// because of it. Entries that would escape the diff dir are skipped, and
+/// failures are logged rather than failing the run.
+pub(crate) fn remove<'a>(diff_dir: &Path, files: impl IntoIterator<Item = &'a PathBuf>) {
+    for file in files {
+        if !file.components().all(|c| matches!(c, Component::Normal(_))) {
+            tracing::warn!(path = %file.display(), "skipping diff manifest entry outside the diff dir");
+            continue;
+        }
+        let path = diff_dir.join(file);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "cannot remove stale diff image");
+                continue;
+            }
+        }
+        // `remove_dir` refuses non-empty directories, which is exactly the
+        // stopping condition.
+        let mut dir = path.parent();
+        while let Some(d) = dir.filter(|d| *d != diff_dir) {
+            if fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
+
+/// Record `files` as this run's diff images. With none written, the
+/// manifest is removed instead so an untouched diff dir isn't created.
+pub(crate) fn save(diff_dir: &Path, files: &BTreeSet<PathBuf>) -> Result<(), CompareError> {
+    let path = diff_dir.join(MANIFEST_NAME);
+    if files.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(CompareError::Write {
+                artifact: Artifact::DiffManifest,
+                path,
+                source: e,
+            }),
+            _ => Ok(()),
+        };
+    }
+    let manifest = DiffManifest {
+        files: files.clone(),
+    };
+    let json = serde_json::to_string_pretty(&manifest).map_err(|source| {
+        CompareError::Serialize {
+            artifact: Artifact::DiffManifest,
+            source,
+        }
+    })?;
+    write_output(Artifact::DiffManifest, &path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_only_tracked_files_and_emptied_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let diff = &tmp.path().join("diff");
+        fs::create_dir_all(diff.join("a/b")).unwrap();
+        fs::create_dir_all(diff.join("keep")).unwrap();
+        fs::write(diff.join("a/b/x.webp"), b"").unwrap();
+        fs::write(diff.join("keep/y.webp"), b"").unwrap();
+        fs::write(diff.join("keep/mine.txt"), b"").unwrap();
+        fs::write(tmp.path().join("outside.webp"), b"").unwrap();
+
+        let tracked: BTreeSet<PathBuf> = ["a/b/x.webp", "keep/y.webp", "../outside.webp"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        save(diff, &tracked).unwrap();
+        remove(diff, &load(diff));
+
+        assert!(!diff.join("a").exists());
+        assert!(!diff.join("keep/y.webp").exists());
+        assert!(diff.join("keep/mine.txt").exists());
+        assert!(tmp.path().join("outside.webp").exists());
+    }
+
+    #[test]
+    fn empty_or_malformed_manifest_tracks_nothing() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(load(tmp.path()).is_empty());
+        fs::write(tmp.path().join(MANIFEST_NAME), b"{oops").unwrap();
+        assert!(load(tmp.path()).is_empty());
+        save(tmp.path(), &BTreeSet::new()).unwrap();
+        assert!(!tmp.path().join(MANIFEST_NAME).exists());
+    }
+}
@@ -1,14 +1,19 @@
 use std::{
-    collections::BTreeSet,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
 };
 
 use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
 use bytes::Bytes;
-use mustache::MapBuilder;
 use serde::{Deserialize, Serialize};
 
-use crate::dir::resolve_dir;
+use crate::{
+    dir::resolve_dir,
+    embed::{self, EmbedImages, ReportImages},
+    thumbnail::{ReportThumbnails, Thumbnails},
+    Artifact, CompareError, Options,
+};
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -25,11 +30,35 @@ pub(crate) struct ReportInput<'a> {
     pub(crate) expected: BTreeSet<PathBuf>,
     pub(crate) actual: BTreeSet<PathBuf>,
     pub(crate) differences: BTreeSet<PathBuf>,
+    pub(crate) renamed: BTreeSet<RenamedItem>,
+    pub(crate) tolerated: BTreeSet<PathBuf>,
+    pub(crate) unsupported: BTreeSet<PathBuf>,
+    pub(crate) format_mismatches: BTreeSet<FormatMismatch>,
+    pub(crate) frames: BTreeMap<PathBuf, FrameReport>,
+    /// Items whose file has another relative path than their name, item
+    /// name → file, per side.
+    pub(crate) actual_paths: BTreeMap<PathBuf, PathBuf>,
+    pub(crate) expected_paths: BTreeMap<PathBuf, PathBuf>,
+    pub(crate) metadata: Option<ReportMetadata>,
     pub(crate) json: &'a Path,
     pub(crate) actual_dir: &'a Path,
     pub(crate) expected_dir: &'a Path,
     pub(crate) diff_dir: &'a Path,
     pub(crate) report: &'a Path,
+    /// User-supplied mustache template replacing the built-in one.
+    pub(crate) template: Option<&'a Path>,
+    /// Link the UI bundle and favicon from files in this directory instead
+    /// of inlining them.
+    pub(crate) assets_dir: Option<&'a Path>,
+    /// Inline the listed images as data URLs so the HTML stands alone.
+    pub(crate) embed: Option<EmbedImages<'a>>,
+    /// Inline at most this many passed items in the HTML.
+    pub(crate) passed_limit: Option<usize>,
+    /// Inline at most this many items per list in the HTML and move the
+    /// rest into sidecar JSON pages.
+    pub(crate) page_size: Option<usize>,
+    /// Thumbnails written for this report, to list in the payload.
+    pub(crate) thumbnails: Option<Thumbnails<'a>>,
     // extendedErrors: boolean,
     pub(crate) url_prefix: Option<url::Url>,
     pub(crate) enable_client_additional_detection: bool,
@@ -37,6 +66,58 @@ pub(crate) struct ReportInput<'a> {
     pub(crate) diff_image_extention: &'static str,
 }
 
+impl<'a> ReportInput<'a> {
+    /// Input for re-rendering a finished report. Image directories are
+    /// taken verbatim from `json` (`from_json`); callers adjust fields that
+    /// differ from `options`.
+    pub(crate) fn from_json_report(
+        json: &'a JsonReport,
+        options: &Options<'a>,
+        report: &'a Path,
+        json_path: &'a Path,
+    ) -> Self {
+        ReportInput {
+            passed: json.passed_items.clone(),
+            failed: json.failed_items.clone(),
+            new: json.new_items.clone(),
+            deleted: json.deleted_items.clone(),
+            actual: json.actual_items.clone(),
+            expected: json.expected_items.clone(),
+            differences: json.diff_items.clone(),
+            renamed: json.renamed_items.clone(),
+            tolerated: json.tolerated_items.clone(),
+            unsupported: json.unsupported_items.clone(),
+            format_mismatches: json.format_mismatches.clone(),
+            frames: json.frame_items.clone(),
+            actual_paths: json.actual_paths.clone(),
+            expected_paths: json.expected_paths.clone(),
+            metadata: json.metadata.clone(),
+            report,
+            template: options.template,
+            assets_dir: options.report_assets_dir,
+            embed: None,
+            passed_limit: options.report_passed_limit,
+            page_size: options.report_page_size,
+            // Re-rendering writes no images, so there are no thumbnails to
+            // serve.
+            thumbnails: None,
+            json: json_path,
+            actual_dir: Path::new(&json.actual_dir),
+            expected_dir: Path::new(&json.expected_dir),
+            diff_dir: Path::new(&json.diff_dir),
+            from_json: true,
+            url_prefix: options.url_prefix.clone(),
+            diff_image_extention: options
+                .diff_image_format
+                .unwrap_or_default()
+                .extension(),
+            enable_client_additional_detection: options
+                .enable_client_additional_detection
+                .unwrap_or(false),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ReportItem {
@@ -54,6 +135,22 @@ impl From<PathBuf> for ReportItem {
     }
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RenamedReportItem {
+    pub(crate) from: ReportItem,
+    pub(crate) to: ReportItem,
+}
+
+impl From<RenamedItem> for RenamedReportItem {
+    fn from(item: RenamedItem) -> Self {
+        RenamedReportItem {
+            from: item.from.into(),
+            to: item.to.into(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct XimgdiffConfig {
@@ -73,11 +170,155 @@ pub(crate) struct ReportJsonInput {
     passed_items: Vec<ReportItem>,
     has_failed: bool,
     failed_items: Vec<ReportItem>,
+    has_renamed: bool,
+    renamed_items: Vec<RenamedReportItem>,
+    has_tolerated: bool,
+    tolerated_items: Vec<ReportItem>,
     actual_dir: PathBuf,
     expected_dir: PathBuf,
     diff_dir: PathBuf,
     diff_image_extention: &'static str,
     ximgdiff_config: XimgdiffConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnails: Option<ReportThumbnails>,
+    /// Per-status counts, present when the lists above don't hold every
+    /// item (`passed_limit` / `page_size`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    totals: Option<ReportMetrics>,
+    /// URLs of the sidecar pages holding the rest of each list, relative to
+    /// the report and keyed by list name (`passedItems`, …).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pages: BTreeMap<&'static str, Vec<String>>,
+}
+
+/// Item counts handed to report templates as `metrics`, so a custom
+/// template can show totals without walking the item lists.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ReportMetrics {
+    total: usize,
+    passed: usize,
+    failed: usize,
+    new: usize,
+    deleted: usize,
+    renamed: usize,
+    tolerated: usize,
+}
+
+/// Everything a report template can reference. The built-in template
+/// mostly uses `js`, `css`, `faviconData` and `report` (the JSON the UI
+/// reads), plus `toleratedDiffs` and `omittedItems`, which the UI has no
+/// view for; custom
+/// templates also get the `ReportJsonInput` fields as mustache data
+/// (`{{#failedItems}}{{raw}}{{/failedItems}}`, `{{#hasNew}}…`) and
+/// `metrics`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TemplateData<'a> {
+    js: &'a str,
+    css: &'a str,
+    favicon_data: String,
+    report: String,
+    /// Set with external assets; the built-in template then links
+    /// `jsHref` / `cssHref` instead of inlining `js` / `css`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    js_href: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    css_href: Option<String>,
+    /// JSON map of images served from other URLs than the UI builds
+    /// (embedded, or stored under another path), and the script serving
+    /// them, when there are any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedded_images: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embed_loader: Option<&'static str>,
+    /// Script loading sidecar pages, when there are any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages_loader: Option<&'static str>,
+    /// The inlined `toleratedItems` with the URL of their diff image.
+    tolerated_diffs: Vec<ToleratedDiff>,
+    /// The inlined `renamedItems` with the URL of their actual image.
+    renamed_images: Vec<RenamedImage>,
+    /// Lists the payload doesn't hold in full, for a notice next to the UI.
+    has_omitted: bool,
+    omitted_items: Vec<OmittedItems>,
+    metrics: ReportMetrics,
+    #[serde(flatten)]
+    input: &'a ReportJsonInput,
+}
+
+#[derive(Serialize)]
+struct OmittedItems {
+    list: &'static str,
+    shown: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct ToleratedDiff {
+    name: String,
+    src: String,
+}
+
+#[derive(Serialize)]
+struct RenamedImage {
+    from: String,
+    to: String,
+    src: String,
+}
+
+/// A deleted expected image paired with a new actual image whose contents
+/// match it. `from` is the expected-side name, `to` the actual-side name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RenamedItem {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// An image whose content doesn't match its extension, found while
+/// discovering images with content sniffing enabled.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FormatMismatch {
+    /// `"actual"` or `"expected"`: which directory `path` is relative to.
+    pub side: String,
+    pub path: PathBuf,
+    /// The file's extension as written, e.g. `png`.
+    pub extension: String,
+    /// What the leading bytes say it is, e.g. `jpg`.
+    pub detected: String,
+}
+
+/// Per-frame outcome for an animated pair compared with `compare_frames`.
+///
+/// Each failed frame `i` of `anim.gif` gets its own diff image in place of
+/// the usual one, named `anim.frame<iii>.<ext>` (zero-padded to three digits).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameReport {
+    pub actual_frames: usize,
+    pub expected_frames: usize,
+    /// Zero-based indices of frames that failed the thresholds. Frames past
+    /// the shorter animation's end aren't compared.
+    pub failed_frames: Vec<usize>,
+}
+
+impl FrameReport {
+    pub fn frame_count_mismatch(&self) -> bool {
+        self.actual_frames != self.expected_frames
+    }
+}
+
+/// Comparison settings that change what "identical" means, recorded so a
+/// reg.json can be interpreted later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportMetadata {
+    /// `"keep"`, `"flatten"` or `"ignore"`.
+    pub alpha: String,
+    /// `#rrggbb`, only for `"flatten"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alpha_background: Option<String>,
+    pub normalize_srgb: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -89,13 +330,78 @@ pub struct JsonReport {
     pub passed_items: BTreeSet<PathBuf>,
     pub expected_items: BTreeSet<PathBuf>,
     pub actual_items: BTreeSet<PathBuf>,
+    /// Failed items' diff images: `<name>.<ext>`, or per-frame
+    /// `<name>.frame<iii>.<ext>` for animations. Tolerated items' diffs are
+    /// listed under `tolerated_items` instead.
     pub diff_items: BTreeSet<PathBuf>,
+    /// Only populated when rename detection is enabled; omitted from
+    /// reg.json when empty so the classic schema stays byte-identical.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub renamed_items: BTreeSet<RenamedItem>,
+    /// Passed items that still had differing pixels within `threshold_*`.
+    /// Only populated with `write_tolerated_diffs`, which also writes their
+    /// diff images; omitted when empty. Always also in `passed_items`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tolerated_items: BTreeSet<PathBuf>,
+    /// Files skipped for their extension; only populated with
+    /// `report_unsupported` and omitted when empty, like `renamed_items`.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub unsupported_items: BTreeSet<PathBuf>,
+    /// Only populated with `sniff_content`; omitted when empty.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub format_mismatches: BTreeSet<FormatMismatch>,
+    /// Animated pairs compared frame by frame (`compare_frames`); omitted
+    /// when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub frame_items: BTreeMap<PathBuf, FrameReport>,
+    /// Animated pairs whose frame counts differ. Always also in
+    /// `failed_items`; omitted when empty.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub frame_count_mismatch_items: BTreeSet<PathBuf>,
+    /// Items whose file under `actual_dir` has another relative path than
+    /// the item name (pairs from a manifest), item name → file; omitted
+    /// when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub actual_paths: BTreeMap<PathBuf, PathBuf>,
+    /// The same under `expected_dir`, for manifests and `path_mappings`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub expected_paths: BTreeMap<PathBuf, PathBuf>,
+    /// Only present when a setting it records differs from the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ReportMetadata>,
     pub actual_dir: String,
     pub expected_dir: String,
     pub diff_dir: String,
 }
 
-fn encode_file_path(file_path: &Path) -> String {
+/// One diff image per failed item, which the report UI lists as its failed
+/// items. An animation only has per-frame diffs, so it's listed by its first.
+fn failure_diffs<'d>(
+    diffs: &'d BTreeSet<PathBuf>,
+    frames: &BTreeMap<PathBuf, FrameReport>,
+) -> Vec<&'d PathBuf> {
+    let animations: BTreeSet<PathBuf> = frames.keys().map(|f| f.with_extension("")).collect();
+    let animation = |diff: &Path| {
+        let frame = diff.with_extension("");
+        let is_frame = frame.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+            e.len() > 5 && e.starts_with("frame") && e[5..].bytes().all(|b| b.is_ascii_digit())
+        });
+        Some(frame.with_extension("")).filter(|a| is_frame && animations.contains(a))
+    };
+    let mut listed = BTreeSet::new();
+    diffs
+        .iter()
+        .filter(|diff| animation(diff).is_none_or(|a| listed.insert(a)))
+        .collect()
+}
+
+/// Relative path of the file holding item `name`, given a side's
+/// `actual_paths` / `expected_paths`.
+pub(crate) fn item_file<'p>(paths: &'p BTreeMap<PathBuf, PathBuf>, name: &'p Path) -> &'p Path {
+    paths.get(name).map_or(name, PathBuf::as_path)
+}
+
+pub(crate) fn encode_file_path(file_path: &Path) -> String {
     file_path
         .display()
         .to_string()
@@ -108,6 +414,38 @@ fn encode_file_path(file_path: &Path) -> String {
 pub(crate) struct Reports {
     pub(crate) json: JsonReport,
     pub(crate) html: Option<Bytes>,
+    /// Files `html` links to, to be written alongside it: external assets
+    /// and sidecar pages, when requested.
+    pub(crate) assets: Vec<ReportAsset>,
+}
+
+pub(crate) struct ReportAsset {
+    pub(crate) path: PathBuf,
+    pub(crate) contents: Cow<'static, [u8]>,
+}
+
+impl ReportAsset {
+    /// `<dir>/<stem>.<hash>.<ext>`. The content hash in the name lets a CDN
+    /// cache assets forever: a new UI build gets a new name.
+    fn hashed(dir: &Path, stem: &str, ext: &str, contents: &'static [u8]) -> Self {
+        // FNV-1a: tiny, and stable across Rust releases unlike `DefaultHasher`.
+        let hash = contents.iter().fold(0xcbf2_9ce4_8422_2325_u64, |h, &b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        ReportAsset {
+            path: dir.join(format!("{stem}.{hash:016x}.{ext}")),
+            contents: Cow::Borrowed(contents),
+        }
+    }
+
+    /// URL of the asset relative to the report at `report`.
+    fn href(&self, report: &Path) -> String {
+        resolve_dir(report, &self.path)
+            .components()
+            .map(|c| urlencoding::encode(&c.as_os_str().to_string_lossy()).into_owned())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
 }
 
 /// Render an image directory path for a reg.json entry, optionally resolved
@@ -141,7 +479,7 @@ pub fn create_dir_for_json_report<'a>(
     }
 }
 
-pub fn create_reports(input: ReportInput) -> Reports {
+pub fn create_reports(input: ReportInput) -> Result<Reports, CompareError> {
     let json_report = JsonReport {
         failed_items: input.failed.clone(),
         new_items: input.new.clone(),
@@ -150,6 +488,20 @@ pub fn create_reports(input: ReportInput) -> Reports {
         expected_items: input.expected.clone(),
         actual_items: input.actual.clone(),
         diff_items: input.differences.clone(),
+        renamed_items: input.renamed.clone(),
+        tolerated_items: input.tolerated.clone(),
+        unsupported_items: input.unsupported.clone(),
+        format_mismatches: input.format_mismatches.clone(),
+        frame_count_mismatch_items: input
+            .frames
+            .iter()
+            .filter(|(_, f)| f.frame_count_mismatch())
+            .map(|(name, _)| name.clone())
+            .collect(),
+        frame_items: input.frames.clone(),
+        actual_paths: input.actual_paths.clone(),
+        expected_paths: input.expected_paths.clone(),
+        metadata: input.metadata.clone(),
         actual_dir: create_dir_for_json_report(
             input.json,
             input.actual_dir,
@@ -163,11 +515,18 @@ pub fn create_reports(input: ReportInput) -> Reports {
         diff_dir: create_dir_for_json_report(input.json, input.diff_dir, input.url_prefix.clone()),
     };
 
+    let mut assets = Vec::new();
     let html_report = {
         let report = input.report;
         let template = include_str!("../../../template/template.html");
         let js = include_str!("../../../report/ui/dist/report.js");
-        let css = include_str!("../../../report/ui/dist/style.css");
+        // The UI's stylesheet, plus the rules for the sections the template
+        // renders itself.
+        let css = concat!(
+            include_str!("../../../report/ui/dist/style.css"),
+            "\n",
+            include_str!("../../../template/template.css"),
+        );
         // Favicon payloads are committed PNG bytes under `report/assets/`.
         // Classic reg-cli embeds them as data URLs too
         // (`src/report.js::loadFaviconAsDataURL`) so the report is a single
@@ -177,45 +536,122 @@ pub fn create_reports(input: ReportInput) -> Reports {
         let favicon_failure: &[u8] =
             include_bytes!("../../../report/assets/favicon_failure.png");
 
-        let json = ReportJsonInput {
+        let metrics = ReportMetrics {
+            total: input.passed.len()
+                + input.failed.len()
+                + input.new.len()
+                + input.deleted.len()
+                + input.renamed.len(),
+            passed: input.passed.len(),
+            failed: input.failed.len(),
+            new: input.new.len(),
+            deleted: input.deleted.len(),
+            renamed: input.renamed.len(),
+            tolerated: input.tolerated.len(),
+        };
+        let dir_href = |dir: &Path| -> PathBuf {
+            if input.from_json {
+                dir.into()
+            } else {
+                resolve_dir(report, dir)
+            }
+        };
+        let actual_dir = dir_href(input.actual_dir);
+        let expected_dir = dir_href(input.expected_dir);
+        let diff_dir = dir_href(input.diff_dir);
+        let embedded_images = {
+            let no_paths = BTreeMap::new();
+            // `diffItems` holds failures only; tolerated diffs are shown too.
+            let diffs: BTreeSet<PathBuf> = input
+                .tolerated
+                .iter()
+                .map(|name| name.with_extension(input.diff_image_extention))
+                .chain(input.differences.iter().cloned())
+                .collect();
+            let dirs = [
+                ReportImages {
+                    href: &actual_dir,
+                    items: &input.actual,
+                    paths: &input.actual_paths,
+                },
+                ReportImages {
+                    href: &expected_dir,
+                    items: &input.expected,
+                    paths: &input.expected_paths,
+                },
+                ReportImages {
+                    href: &diff_dir,
+                    items: &diffs,
+                    paths: &no_paths,
+                },
+            ];
+            let dirs = [&dirs[0], &dirs[1], &dirs[2]];
+            let images = match (&input.embed, &input.thumbnails) {
+                (Some(embed), _) => embed.collect(dirs[0], dirs[1], dirs[2]),
+                (None, Some(thumbnails)) => {
+                    // Thumbnails point at the real files themselves, so they
+                    // replace the redirects for the items they cover.
+                    let mut images = embed::redirects(dirs);
+                    images.extend(thumbnails.images(report, dirs));
+                    images
+                }
+                (None, None) => embed::redirects(dirs),
+            };
+            if images.is_empty() {
+                None
+            } else {
+                Some(embed::to_script_json(&images).map_err(|source| {
+                    CompareError::Serialize {
+                        artifact: Artifact::Report,
+                        source,
+                    }
+                })?)
+            }
+        };
+        let new = input.new;
+        let deleted = input.deleted;
+        let failure_diffs = failure_diffs(&input.differences, &input.frames);
+        let mut json = ReportJsonInput {
             r#type: if input.failed.is_empty() {
                 ReportStatus::Success
             } else {
                 ReportStatus::Danger
             },
-            has_new: !input.new.is_empty(),
-            new_items: input.new.into_iter().map(ReportItem::from).collect(),
-            has_deleted: !input.deleted.is_empty(),
-            deleted_items: input.deleted.into_iter().map(ReportItem::from).collect(),
+            has_new: !new.is_empty(),
+            new_items: new.into_iter().map(ReportItem::from).collect(),
+            has_deleted: !deleted.is_empty(),
+            deleted_items: deleted.into_iter().map(ReportItem::from).collect(),
             has_passed: !input.passed.is_empty(),
             passed_items: input.passed.into_iter().map(ReportItem::from).collect(),
-            has_failed: !input.differences.is_empty(),
-            failed_items: input
-                .differences
+            has_failed: !failure_diffs.is_empty(),
+            failed_items: failure_diffs
                 .into_iter()
-                .map(ReportItem::from)
+                .map(|diff| ReportItem::from(diff.clone()))
                 .collect(),
-            actual_dir: if input.from_json {
-                input.actual_dir.into()
-            } else {
-                resolve_dir(report, input.actual_dir).into()
-            },
-            expected_dir: if input.from_json {
-                input.expected_dir.into()
-            } else {
-                resolve_dir(report, input.expected_dir).into()
-            },
-            diff_dir: if input.from_json {
-                input.diff_dir.into()
-            } else {
-                resolve_dir(report, input.diff_dir).into()
-            },
+            has_renamed: !input.renamed.is_empty(),
+            renamed_items: input
+                .renamed
+                .into_iter()
+                .map(RenamedReportItem::from)
+                .collect(),
+            has_tolerated: !input.tolerated.is_empty(),
+            tolerated_items: input.tolerated.into_iter().map(ReportItem::from).collect(),
+            actual_dir,
+            expected_dir,
+            diff_dir,
             diff_image_extention: input.diff_image_extention,
             ximgdiff_config: XimgdiffConfig {
                 enabled: input.enable_client_additional_detection,
                 worker_url: "./worker.js".to_string(),
             },
+            thumbnails: input.thumbnails.as_ref().map(|t| t.for_report(report)),
+            totals: None,
+            pages: BTreeMap::new(),
         };
+        let pages = paginate(&mut json, report, input.passed_limit, input.page_size)?;
+        if json.passed_items.len() < metrics.passed || !json.pages.is_empty() {
+            json.totals = Some(metrics);
+        }
 
         // Render with base64-encoded PNG bytes so the `<link rel="shortcut
         // icon" href="{{&faviconData}}">` placeholder gets a self-contained
@@ -226,31 +662,205 @@ pub fn create_reports(input: ReportInput) -> Reports {
             ReportStatus::Success => favicon_success,
             ReportStatus::Danger => favicon_failure,
         };
-        let favicon_data = format!(
-            "data:image/png;base64,{}",
-            BASE64_STANDARD.encode(favicon_bytes)
-        );
-        let data = MapBuilder::new()
-            .insert_str("js", js)
-            .insert_str("css", css)
-            .insert_str("faviconData", favicon_data)
-            .insert_str(
-                "report",
-                serde_json::to_string(&json).expect("should convert."),
-            )
-            .build();
-        let template = mustache::compile_str(template).expect("should compile template.");
-        let mut html = vec![];
-        template
-            .render_data(&mut html, &data)
-            .expect("should render report.");
+        let (favicon_data, js_href, css_href) = match input.assets_dir {
+            Some(dir) => {
+                let favicon = ReportAsset::hashed(
+                    dir,
+                    match &json.r#type {
+                        ReportStatus::Success => "favicon_success",
+                        ReportStatus::Danger => "favicon_failure",
+                    },
+                    "png",
+                    favicon_bytes,
+                );
+                let js = ReportAsset::hashed(dir, "report", "js", js.as_bytes());
+                let css = ReportAsset::hashed(dir, "style", "css", css.as_bytes());
+                let hrefs = (favicon.href(report), Some(js.href(report)), Some(css.href(report)));
+                assets = vec![favicon, js, css];
+                hrefs
+            }
+            None => (
+                format!(
+                    "data:image/png;base64,{}",
+                    BASE64_STANDARD.encode(favicon_bytes)
+                ),
+                None,
+                None,
+            ),
+        };
+        assets.extend(pages);
+        let report_json = serde_json::to_string(&json).map_err(|source| CompareError::Serialize {
+            artifact: Artifact::Report,
+            source,
+        })?;
+        let omitted_items: Vec<_> = [
+            ("passed", json.passed_items.len(), metrics.passed),
+            ("renamed", json.renamed_items.len(), metrics.renamed),
+            ("tolerated", json.tolerated_items.len(), metrics.tolerated),
+        ]
+        .into_iter()
+        .filter(|&(_, shown, total)| shown < total)
+        .map(|(list, shown, total)| OmittedItems { list, shown, total })
+        .collect();
+        let data = mustache::to_data(TemplateData {
+            js,
+            css,
+            favicon_data,
+            report: report_json,
+            js_href,
+            css_href,
+            embed_loader: embedded_images.is_some().then_some(embed::LOADER),
+            embedded_images,
+            pages_loader: (!json.pages.is_empty()).then_some(PAGES_LOADER),
+            tolerated_diffs: json
+                .tolerated_items
+                .iter()
+                .map(|item| ToleratedDiff {
+                    name: item.raw.clone(),
+                    src: format!(
+                        "{}/{}",
+                        json.diff_dir.display(),
+                        encode_file_path(
+                            &Path::new(&item.raw).with_extension(input.diff_image_extention)
+                        )
+                    ),
+                })
+                .collect(),
+            renamed_images: json
+                .renamed_items
+                .iter()
+                .map(|item| RenamedImage {
+                    from: item.from.raw.clone(),
+                    to: item.to.raw.clone(),
+                    src: format!("{}/{}", json.actual_dir.display(), item.to.encoded),
+                })
+                .collect(),
+            has_omitted: !omitted_items.is_empty(),
+            omitted_items,
+            metrics,
+            input: &json,
+        })
+        .map_err(|e| CompareError::Render {
+            artifact: Artifact::Report,
+            reason: e.to_string(),
+        })?;
+        let html = match input.template {
+            Some(path) => {
+                let source = std::fs::read_to_string(path).map_err(|source| {
+                    CompareError::Read {
+                        path: path.to_path_buf(),
+                        source,
+                    }
+                })?;
+                render_template(&source, &data, Some(path))?
+            }
+            None => render_template(template, &data, None)?,
+        };
         Some(html.into())
     };
 
-    Reports {
+    Ok(Reports {
         json: json_report,
         html: html_report,
+        assets,
+    })
+}
+
+/// Loader for sidecar pages, added to the report when there are any.
+const PAGES_LOADER: &str = include_str!("../../../template/pages.js");
+
+/// `<report stem>.pages/` next to `report`, holding its sidecar pages.
+pub(crate) fn pages_dir(report: &Path) -> PathBuf {
+    let stem = report.file_stem().unwrap_or_default().to_string_lossy();
+    report.with_file_name(format!("{stem}.pages"))
+}
+
+/// Drop passed items past `passed_limit`, then move everything past the
+/// first `page_size` items of the passed, renamed and tolerated lists into
+/// `<report stem>.pages/` next to `report`, recording the page URLs in
+/// `json.pages`. Returns the pages to write. Failed, new and deleted items
+/// are what a report is read for, so they are always inlined in full.
+fn paginate(
+    json: &mut ReportJsonInput,
+    report: &Path,
+    passed_limit: Option<usize>,
+    page_size: Option<usize>,
+) -> Result<Vec<ReportAsset>, CompareError> {
+    if let Some(limit) = passed_limit {
+        json.passed_items.truncate(limit);
     }
+    let Some(size) = page_size.filter(|&size| size > 0) else {
+        return Ok(Vec::new());
+    };
+    let mut pager = Pager {
+        size,
+        dir: pages_dir(report),
+        report,
+        hrefs: BTreeMap::new(),
+        pages: Vec::new(),
+    };
+    pager.split("passedItems", &mut json.passed_items)?;
+    pager.split("renamedItems", &mut json.renamed_items)?;
+    pager.split("toleratedItems", &mut json.tolerated_items)?;
+    json.pages = pager.hrefs;
+    Ok(pager.pages)
+}
+
+struct Pager<'a> {
+    size: usize,
+    dir: PathBuf,
+    report: &'a Path,
+    hrefs: BTreeMap<&'static str, Vec<String>>,
+    pages: Vec<ReportAsset>,
+}
+
+impl Pager<'_> {
+    /// Keep the first page of `items` and turn the rest into page files.
+    fn split<T: Serialize>(
+        &mut self,
+        name: &'static str,
+        items: &mut Vec<T>,
+    ) -> Result<(), CompareError> {
+        if items.len() <= self.size {
+            return Ok(());
+        }
+        let rest = items.split_off(self.size);
+        for (i, chunk) in rest.chunks(self.size).enumerate() {
+            let body = serde_json::to_vec(chunk).map_err(|source| CompareError::Serialize {
+                artifact: Artifact::ReportAsset,
+                source,
+            })?;
+            let page = ReportAsset {
+                path: self.dir.join(format!("{name}-{}.json", i + 1)),
+                contents: Cow::Owned(body),
+            };
+            self.hrefs.entry(name).or_default().push(page.href(self.report));
+            self.pages.push(page);
+        }
+        Ok(())
+    }
+}
+
+/// Compile and render the HTML report template. Both steps can fail on a
+/// malformed template, so neither may panic. `path` is the custom template
+/// the source came from, for error messages.
+fn render_template(
+    template: &str,
+    data: &mustache::Data,
+    path: Option<&Path>,
+) -> Result<Vec<u8>, CompareError> {
+    let template = mustache::compile_str(template).map_err(|e| CompareError::Template {
+        path: path.map(Path::to_path_buf),
+        reason: e.to_string(),
+    })?;
+    let mut html = vec![];
+    template
+        .render_data(&mut html, data)
+        .map_err(|e| CompareError::Render {
+            artifact: Artifact::Report,
+            reason: e.to_string(),
+        })?;
+    Ok(html)
 }
 
 /// Build a JUnit XML document byte-compatible with classic reg-cli's
@@ -274,6 +884,8 @@ pub fn create_reports(input: ReportInput) -> Reports {
 ///     ONLY when `extended_errors` is set; otherwise they are reported as
 ///     passed testcases.
 ///   - `passedItems` emit bare `<testcase name="..."/>`.
+///   - `renamedItems` (not in classic) follow as passed testcases under
+///     their new name.
 ///   - `tests` / `failures` attributes appear on BOTH `<testsuites>` and the
 ///     nested `<testsuite>`.
 ///   - Output is pretty-printed with 2-space indent, no `encoding=`
@@ -311,6 +923,9 @@ pub(crate) fn build_junit_xml(report: &JsonReport, extended_errors: bool) -> Str
     for p in &report.passed_items {
         passed_names.push(p.display().to_string());
     }
+    for r in &report.renamed_items {
+        passed_names.push(r.to.display().to_string());
+    }
 
     let failures = failure_cases.len();
     let tests = passed_names.len() + failures;
@@ -358,6 +973,12 @@ pub(crate) fn build_junit_xml(report: &JsonReport, extended_errors: bool) -> Str
             esc(&p.display().to_string())
         ));
     }
+    for r in &report.renamed_items {
+        cases.push(format!(
+            "    <testcase name=\"{}\"/>",
+            esc(&r.to.display().to_string())
+        ));
+    }
 
     // No encoding attr, no trailing newline — matches xmlbuilder2's default
     // when created with `{ version: '1.0' }` and rendered with `prettyPrint: true`.
@@ -376,6 +997,7 @@ pub(crate) fn build_junit_xml(report: &JsonReport, extended_errors: bool) -> Str
 #[cfg(test)]
 mod tests {
     use super::*;
+    use mustache::MapBuilder;
 
     fn mk_report(
         passed: &[&str],
@@ -395,6 +1017,15 @@ mod tests {
             expected_items: BTreeSet::new(),
             actual_items: BTreeSet::new(),
             diff_items: BTreeSet::new(),
+            renamed_items: BTreeSet::new(),
+            tolerated_items: BTreeSet::new(),
+            unsupported_items: BTreeSet::new(),
+            format_mismatches: BTreeSet::new(),
+            frame_items: BTreeMap::new(),
+            frame_count_mismatch_items: BTreeSet::new(),
+            actual_paths: BTreeMap::new(),
+            expected_paths: BTreeMap::new(),
+            metadata: None,
             actual_dir: String::new(),
             expected_dir: String::new(),
             diff_dir: String::new(),
@@ -480,6 +1111,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn junit_renamed_items_are_passed_under_new_name() {
+        let mut r = mk_report(&["ok.png"], &[], &[], &[]);
+        r.renamed_items.insert(RenamedItem {
+            from: "old.png".into(),
+            to: "new.png".into(),
+        });
+        let xml = build_junit_xml(&r, true);
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0"?>
+<testsuites name="reg-cli tests" tests="2" failures="0">
+  <testsuite name="reg-cli" tests="2" failures="0">
+    <testcase name="ok.png"/>
+    <testcase name="new.png"/>
+  </testsuite>
+</testsuites>"#
+        );
+    }
+
+    #[test]
+    fn json_report_omits_empty_renamed_items() {
+        let r = mk_report(&[], &[], &[], &[]);
+        let json = serde_json::to_string(&r).unwrap();
+        assert!(!json.contains("renamedItems"));
+        let back: JsonReport = serde_json::from_str(&json).unwrap();
+        assert!(back.renamed_items.is_empty());
+    }
+
     #[test]
     fn junit_escapes_xml_special_chars_in_name() {
         let r = mk_report(&[], &[r#"a&b<c>d".png"#], &[], &[]);
@@ -501,4 +1161,14 @@ mod tests {
 </testsuites>"#
         );
     }
+
+    #[test]
+    fn bad_template_is_an_error_not_a_panic() {
+        let data = MapBuilder::new().insert_str("report", "{}").build();
+        assert!(matches!(
+            render_template("<p>{{#report}}unclosed</p>", &data, None),
+            Err(CompareError::Template { .. })
+        ));
+        assert_eq!(render_template("{{&report}}", &data, None).unwrap(), b"{}");
+    }
 }
@@ -0,0 +1,310 @@
+//! Thread-count planning for the per-image diff loop.
+//!
+//! Classic reg-cli forced a single worker below 20 images and otherwise used
+//! a fixed pool size. Image count is a poor proxy for work though: four
+//! 4000×12000 full-page captures cost far more than fifty 64×64 icons. We
+//! instead read each target's dimensions from its header (no decode) and
+//! size the pool by total pixel area, capped by the machine's available
+//! parallelism. An explicit `concurrency` from the caller always wins and
+//! skips the probing entirely.
+
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::CompareError;
+
+/// Roughly one 1920×1080 frame. Below this much pixel work per extra
+/// thread, rayon spin-up and cross-thread span propagation cost more than
+/// the parallelism buys back.
+const MIN_AREA_PER_THREAD: u64 = 2_000_000;
+
+/// Header bytes read per image when probing dimensions. PNG/GIF/BMP/WebP
+/// keep their size in the first 30 bytes; JPEG's SOF marker usually sits
+/// after EXIF/ICC segments, which fit comfortably in 64 KiB.
+const PROBE_LEN: u64 = 64 * 1024;
+
+/// Used when `available_parallelism` is unsupported (some WASI hosts).
+/// Matches the previous fixed default.
+const FALLBACK_PARALLELISM: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PlanSource {
+    /// Caller passed `concurrency` explicitly.
+    Explicit,
+    /// Derived from pixel area and available parallelism.
+    Auto,
+}
+
+impl PlanSource {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            PlanSource::Explicit => "explicit",
+            PlanSource::Auto => "auto",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ConcurrencyPlan {
+    pub(crate) threads: usize,
+    pub(crate) source: PlanSource,
+    /// Sum of probed pixel areas. Zero for explicit plans (we don't probe).
+    pub(crate) total_area: u64,
+}
+
+/// Number of worker threads the host can usefully run.
+pub(crate) fn available_parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(FALLBACK_PARALLELISM)
+}
+
+/// The most recently used pool. Building a pool spawns OS threads (WASI
+/// threads under Wasm), so repeated `run` / `Comparer` calls with the same
+/// plan should land on warm workers; keeping only one means a long-lived
+/// host whose plans vary never holds more than one idle pool. Comparers
+/// pinned to an explicit size keep theirs alive through their own `Arc`.
+static POOL: Lazy<PoolCache> = Lazy::new(PoolCache::default);
+
+/// Fetch the shared pool if it has `threads` workers, otherwise build one
+/// and make it the shared pool.
+pub(crate) fn shared_pool(threads: usize) -> Result<Arc<ThreadPool>, CompareError> {
+    POOL.get(threads)
+}
+
+/// A single cached pool, replaced whenever a different size is asked for.
+#[derive(Default)]
+struct PoolCache(Mutex<Option<Arc<ThreadPool>>>);
+
+impl PoolCache {
+    fn get(&self, threads: usize) -> Result<Arc<ThreadPool>, CompareError> {
+        let threads = threads.max(1);
+        let mut pool = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(pool) = pool.as_ref().filter(|p| p.current_num_threads() == threads) {
+            return Ok(pool.clone());
+        }
+        let built = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| CompareError::ThreadPool {
+                threads,
+                reason: e.to_string(),
+            })?;
+        Ok(pool.insert(Arc::new(built)).clone())
+    }
+}
+
+/// Decide how many rayon workers to use for a batch whose actual images live
+/// at `actual_paths`. Only the actual side is probed: both sides of a pair
+/// are normally the same size, and halving the header reads matters on
+/// large suites.
+pub(crate) fn plan_concurrency<'p>(
+    requested: Option<usize>,
+    actual_paths: impl ExactSizeIterator<Item = &'p Path>,
+) -> ConcurrencyPlan {
+    if let Some(n) = requested {
+        return ConcurrencyPlan {
+            threads: n.max(1),
+            source: PlanSource::Explicit,
+            total_area: 0,
+        };
+    }
+    let target_count = actual_paths.len();
+    let total_area = actual_paths.map(image_area).fold(0u64, u64::saturating_add);
+    ConcurrencyPlan {
+        threads: threads_for_area(total_area, target_count, available_parallelism()),
+        source: PlanSource::Auto,
+        total_area,
+    }
+}
+
+fn threads_for_area(total_area: u64, target_count: usize, max_threads: usize) -> usize {
+    let by_area = total_area.div_ceil(MIN_AREA_PER_THREAD).max(1);
+    let by_area = usize::try_from(by_area).unwrap_or(usize::MAX);
+    by_area.min(target_count).min(max_threads).max(1)
+}
+
+/// Pixel area of the image at `path`, read from its header. Formats whose
+/// header we don't parse (TIFF) or files we can't read fall back to the file
+/// length — compressed screenshots are well under one byte per pixel, so
+/// this under-estimates rather than over-parallelises.
+pub(crate) fn image_area(path: &Path) -> u64 {
+    let Ok(file) = File::open(path) else {
+        return 0;
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut head = Vec::with_capacity(PROBE_LEN.min(len) as usize);
+    if file.take(PROBE_LEN).read_to_end(&mut head).is_err() {
+        return len;
+    }
+    match image_dimensions(&head) {
+        Some((w, h)) => u64::from(w) * u64::from(h),
+        None => len,
+    }
+}
+
+/// Width and height from the leading bytes of a PNG, GIF, BMP, WebP or JPEG.
+pub(crate) fn image_dimensions(buf: &[u8]) -> Option<(u32, u32)> {
+    let be32 = |at: usize| -> Option<u32> {
+        buf.get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let le16 = |at: usize| -> Option<u32> {
+        buf.get(at..at + 2)
+            .map(|b| u32::from(u16::from_le_bytes([b[0], b[1]])))
+    };
+    let le24 = |at: usize| -> Option<u32> {
+        buf.get(at..at + 3)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], 0]))
+    };
+
+    if buf.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if buf.starts_with(b"GIF87a") || buf.starts_with(b"GIF89a") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if buf.starts_with(b"BM") {
+        let w = i32::from_le_bytes(buf.get(18..22)?.try_into().ok()?);
+        let h = i32::from_le_bytes(buf.get(22..26)?.try_into().ok()?);
+        return Some((w.unsigned_abs(), h.unsigned_abs()));
+    }
+    if buf.starts_with(b"RIFF") && buf.get(8..12) == Some(&b"WEBP"[..]) {
+        return match buf.get(12..16)? {
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(buf.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            _ => None,
+        };
+    }
+    if buf.starts_with(&[0xff, 0xd8]) {
+        return jpeg_dimensions(buf);
+    }
+    None
+}
+
+fn jpeg_dimensions(buf: &[u8]) -> Option<(u32, u32)> {
+    let mut i = 2;
+    while i + 4 <= buf.len() {
+        if buf[i] != 0xff {
+            return None;
+        }
+        let marker = buf[i + 1];
+        // Fill bytes between segments.
+        if marker == 0xff {
+            i += 1;
+            continue;
+        }
+        let seg_len = usize::from(u16::from_be_bytes([buf[i + 2], buf[i + 3]]));
+        // SOF0..SOF15, excluding DHT (C4), JPG (C8) and DAC (CC).
+        if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let h = u16::from_be_bytes([*buf.get(i + 5)?, *buf.get(i + 6)?]);
+            let w = u16::from_be_bytes([*buf.get(i + 7)?, *buf.get(i + 8)?]);
+            return Some((u32::from(w), u32::from(h)));
+        }
+        i += 2 + seg_len;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn explicit_concurrency_is_honoured_for_small_suites() {
+        let plan = plan_concurrency(Some(8), [Path::new("a.png")].into_iter());
+        assert_eq!(plan.threads, 8);
+        assert_eq!(plan.source, PlanSource::Explicit);
+    }
+
+    #[test]
+    fn explicit_zero_is_clamped_to_one() {
+        let plan = plan_concurrency(Some(0), std::iter::empty());
+        assert_eq!(plan.threads, 1);
+    }
+
+    #[test]
+    fn default_plan_is_sized_by_area() {
+        let tmp = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..3)
+            .map(|i| {
+                let path = tmp.path().join(format!("{i}.png"));
+                image::RgbaImage::new(2000, 1000).save(&path).unwrap();
+                path
+            })
+            .collect();
+        let plan = plan_concurrency(None, paths.iter().map(PathBuf::as_path));
+        assert_eq!(plan.source, PlanSource::Auto);
+        assert_eq!(plan.total_area, 6_000_000);
+        assert_eq!(plan.threads, 3.min(available_parallelism()));
+    }
+
+    #[test]
+    fn pool_cache_keeps_only_the_latest_size() {
+        // A private cache, since other tests use the shared one.
+        let cache = PoolCache::default();
+        let first = Arc::downgrade(&cache.get(3).unwrap());
+        assert!(first.upgrade().is_some());
+
+        let second = cache.get(5).unwrap();
+        assert_eq!(second.current_num_threads(), 5);
+        assert!(first.upgrade().is_none(), "the 3-thread pool was not evicted");
+        assert!(Arc::ptr_eq(&second, &cache.get(5).unwrap()));
+    }
+
+    #[test]
+    fn many_tiny_images_stay_single_threaded() {
+        assert_eq!(threads_for_area(100 * 64 * 64, 100, 16), 1);
+    }
+
+    #[test]
+    fn few_huge_images_still_parallelise() {
+        let area = 4 * 4000 * 12000;
+        assert_eq!(threads_for_area(area, 4, 16), 4);
+        assert_eq!(threads_for_area(area, 4, 2), 2);
+    }
+
+    #[test]
+    fn png_header_dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&1280u32.to_be_bytes());
+        png.extend_from_slice(&720u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((1280, 720)));
+    }
+
+    #[test]
+    fn gif_header_dimensions() {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&300u16.to_le_bytes());
+        gif.extend_from_slice(&200u16.to_le_bytes());
+        assert_eq!(image_dimensions(&gif), Some((300, 200)));
+    }
+
+    #[test]
+    fn jpeg_skips_app_segments_to_sof() {
+        let jpeg = [
+            0xff, 0xd8, // SOI
+            0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, // APP0, 2 payload bytes
+            0xff, 0xc0, 0x00, 0x11, 0x08, 0x02, 0x58, 0x03, 0x20, // SOF0 600×800
+        ];
+        assert_eq!(image_dimensions(&jpeg), Some((800, 600)));
+    }
+
+    #[test]
+    fn unknown_format_has_no_dimensions() {
+        assert_eq!(image_dimensions(b"II*\0 tiff is not parsed"), None);
+    }
+}
@@ -0,0 +1,201 @@
+//! Downscaled copies of report images.
+//!
+//! With `Options::thumbnail_dir`, `run` writes a thumbnail of every actual,
+//! expected and diff image the report lists to
+//! `<thumbnail_dir>/{actual,expected,diff}/<name>.<ext>` (the image's own
+//! name with the thumbnail extension appended, so `a.png` and `a.jpg` don't
+//! collide). The report serves them in place of the originals through
+//! `window['__reg_images__']` (see `embed`), loading the full-size image
+//! only once one is drawn larger than its thumbnail, and the payload's
+//! `thumbnails` gives the directories for custom UIs. The same downscaling
+//! backs embedded thumbnails (`Options::embed_thumbnail_size`).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use image::DynamicImage;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    dir::resolve_dir,
+    embed::{EmbeddedImage, ReportImages},
+    report::encode_file_path,
+    write_output, Artifact, CompareError, DiffImageFormat, Options,
+};
+
+/// Longest side of generated thumbnails unless `Options::thumbnail_size`
+/// says otherwise.
+pub(crate) const DEFAULT_SIZE: u32 = 320;
+
+/// `bytes` decoded and scaled down to fit within `max` × `max`, and whether
+/// that took any scaling; images that already fit keep their size. `None`
+/// when the image can't be decoded here (SVG, or AVIF without the feature).
+pub(crate) fn resize(bytes: &[u8], max: u32) -> Option<(DynamicImage, bool)> {
+    let img = image::load_from_memory(bytes).ok()?;
+    if img.width() <= max && img.height() <= max {
+        return Some((img, false));
+    }
+    Some((img.thumbnail(max, max), true))
+}
+
+pub(crate) fn encode(img: &DynamicImage, format: DiffImageFormat) -> Option<Vec<u8>> {
+    let format = match format {
+        DiffImageFormat::Webp => image::ImageFormat::WebP,
+        DiffImageFormat::Png => image::ImageFormat::Png,
+    };
+    let mut out = Cursor::new(Vec::new());
+    // The WebP encoder only takes 8-bit RGB(A).
+    DynamicImage::ImageRgba8(img.to_rgba8())
+        .write_to(&mut out, format)
+        .ok()?;
+    Some(out.into_inner())
+}
+
+/// Thumbnail settings for a run, and the thumbnails written so far.
+pub(crate) struct Thumbnails<'a> {
+    dir: &'a Path,
+    size: u32,
+    format: DiffImageFormat,
+    /// Report names with a thumbnail, by image directory.
+    written: BTreeMap<&'static str, BTreeSet<PathBuf>>,
+}
+
+/// Where the report UI finds thumbnails: `<dir>/<encoded name>.<extension>`
+/// for each of the image directories.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ReportThumbnails {
+    actual_dir: PathBuf,
+    expected_dir: PathBuf,
+    diff_dir: PathBuf,
+    extension: &'static str,
+}
+
+impl<'a> Thumbnails<'a> {
+    pub(crate) fn from_options(options: &Options<'a>) -> Option<Self> {
+        Some(Thumbnails {
+            dir: options.thumbnail_dir?,
+            size: options.thumbnail_size.unwrap_or(DEFAULT_SIZE),
+            format: options.thumbnail_format.unwrap_or_default(),
+            written: BTreeMap::new(),
+        })
+    }
+
+    /// Thumbnail path for image `name`, relative to its kind's directory.
+    fn file_name(&self, name: &Path) -> PathBuf {
+        let mut file = OsString::from(name.as_os_str());
+        file.push(".");
+        file.push(self.format.extension());
+        file.into()
+    }
+
+    /// Thumbnail path for image `name` from the `kind` directory.
+    fn path(&self, kind: &str, name: &Path) -> PathBuf {
+        self.dir.join(kind).join(self.file_name(name))
+    }
+
+    /// Write thumbnails for `images`, pairs of report name and source file,
+    /// under the `kind` directory. Images that can't be read or decoded
+    /// are skipped with a warning; failing to write is an error.
+    pub(crate) fn write(
+        &mut self,
+        kind: &'static str,
+        images: &[(PathBuf, PathBuf)],
+    ) -> Result<(), CompareError> {
+        let written: Vec<Option<&PathBuf>> = images
+            .par_iter()
+            .map(|(name, source)| {
+                let thumbnail = std::fs::read(source)
+                    .ok()
+                    .and_then(|bytes| resize(&bytes, self.size))
+                    .and_then(|(img, _)| encode(&img, self.format));
+                match thumbnail {
+                    Some(bytes) => {
+                        write_output(Artifact::Thumbnail, self.path(kind, name), bytes)?;
+                        Ok(Some(name))
+                    }
+                    None => {
+                        tracing::warn!(path = %source.display(), "cannot make thumbnail; skipping");
+                        Ok(None)
+                    }
+                }
+            })
+            .collect::<Result<_, CompareError>>()?;
+        self.written
+            .entry(kind)
+            .or_default()
+            .extend(written.into_iter().flatten().cloned());
+        Ok(())
+    }
+
+    /// Entries for `window['__reg_images__']` serving each written thumbnail
+    /// in place of its image, with the image itself as the full-size URL.
+    /// `dirs` are the actual, expected and diff directories of a report at
+    /// `report`.
+    pub(crate) fn images(
+        &self,
+        report: &Path,
+        dirs: [&ReportImages; 3],
+    ) -> BTreeMap<String, EmbeddedImage> {
+        let mut images = BTreeMap::new();
+        for (kind, dir) in ["actual", "expected", "diff"].into_iter().zip(dirs) {
+            let Some(names) = self.written.get(kind) else {
+                continue;
+            };
+            let href = resolve_dir(report, &self.dir.join(kind));
+            for name in names {
+                let src = format!("{}/{}", href.display(), encode_file_path(&self.file_name(name)));
+                images.insert(dir.url(name), EmbeddedImage::thumbnail(src, dir.file_url(name)));
+            }
+        }
+        images
+    }
+
+    /// The payload entry for a report at `report`.
+    pub(crate) fn for_report(&self, report: &Path) -> ReportThumbnails {
+        ReportThumbnails {
+            actual_dir: resolve_dir(report, &self.dir.join("actual")),
+            expected_dir: resolve_dir(report, &self.dir.join("expected")),
+            diff_dir: resolve_dir(report, &self.dir.join("diff")),
+            extension: self.format.extension(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnails_fit_the_box_and_keep_the_source_name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("big.png");
+        image::RgbaImage::new(100, 400).save(&source).unwrap();
+        let dir = tmp.path().join("thumbs");
+        let mut thumbnails = Thumbnails {
+            dir: &dir,
+            size: 40,
+            format: DiffImageFormat::Png,
+            written: BTreeMap::new(),
+        };
+        let images = [
+            (PathBuf::from("a/big.png"), source),
+            (PathBuf::from("missing.png"), tmp.path().join("missing.png")),
+        ];
+        thumbnails.write("actual", &images).unwrap();
+
+        let thumb = image::open(dir.join("actual/a/big.png.png")).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (10, 40));
+        assert!(!dir.join("actual/missing.png.png").exists());
+        assert_eq!(thumbnails.written["actual"], [PathBuf::from("a/big.png")].into());
+
+        let payload = thumbnails.for_report(&tmp.path().join("report.html"));
+        assert_eq!(payload.actual_dir, Path::new("thumbs/actual"));
+        assert_eq!(payload.extension, "png");
+    }
+}
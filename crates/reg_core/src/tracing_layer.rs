@@ -266,14 +266,22 @@ impl tracing::field::Visit for AttributeVisitor {
     }
 }
 
-/// Initialize tracing with the collector layer
+/// Initialize tracing with the collector layer, and print error events
+/// (e.g. images that failed to compare) to stderr so CLI users see them.
 pub fn init_tracing() {
-    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*};
     
     let collector_layer = CollectorLayer::new();
+    let stderr_layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(false)
+        .with_target(false)
+        .without_time()
+        .with_filter(LevelFilter::ERROR);
     
     tracing_subscriber::registry()
         .with(collector_layer)
+        .with(stderr_layer)
         .init();
 }
 
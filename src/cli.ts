@@ -46,7 +46,7 @@ const HELP = `
     -M, --matchingThreshold   YIQ threshold (0-1). Default 0.
     -T, --thresholdRate       Ratio of pixels that may differ before failing.
     -S, --thresholdPixel      Absolute pixel count that may differ before failing.
-    -C, --concurrency         Parallel worker count. Default 4.
+    -C, --concurrency         Parallel worker count. Default: auto.
     -A, --enableAntialias     Count anti-aliased pixels as different.
     -D, --customDiffMessage   Trailing message printed on diff.
         --junit               Path to write a JUnit XML test report.
@@ -234,8 +234,9 @@ emitter.once('complete', async (data: CompareOutput) => {
   }
 });
 
-emitter.once('error', (err: Error) => {
-  process.stderr.write(`reg-cli: ${err?.message ?? String(err)}\n`);
+emitter.once('error', (err: Error & { code?: string }) => {
+  const code = typeof err?.code === 'string' ? `error[${err.code}]: ` : '';
+  process.stderr.write(`reg-cli: ${code}${err?.message ?? String(err)}\n`);
   process.exitCode = 1;
 });
 
@@ -141,6 +141,12 @@ const runInternal = async (argv: string[], emitter: EventEmitter): Promise<void>
     }
 
     workers.forEach((w) => w.terminate());
+    // `wasm_main` returns failures as `{ error: { code, stage, message, path } }`
+    // rather than aborting the worker.
+    if (data && data.error) {
+      emitter.emit('error', Object.assign(new Error(data.error.message), data.error));
+      return;
+    }
     emitter.emit('complete', data);
   });
 };
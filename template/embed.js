@@ -0,0 +1,54 @@
+// Serves report images from `window['__reg_images__']` (see
+// crates/reg_core/src/embed.rs). The UI builds image URLs from
+// actualDir/expectedDir/diffDir as usual; any <img> whose src matches an
+// embedded entry is pointed at its data URL instead. Entries with `full` are
+// thumbnails: once one is drawn larger than its own pixels (e.g. in the
+// detail view), the full-size image is loaded from `full`.
+(function () {
+  var images = window['__reg_images__'] || {};
+  var byHref = {};
+  Object.keys(images).forEach(function (key) {
+    byHref[new URL(key, document.baseURI).href] = images[key];
+  });
+
+  var upscaled = window.ResizeObserver
+    ? new ResizeObserver(function (entries) {
+        entries.forEach(function (entry) {
+          var img = entry.target;
+          if (img.naturalWidth && img.clientWidth > img.naturalWidth) {
+            upscaled.unobserve(img);
+            img.src = img.getAttribute('data-reg-full');
+          }
+        });
+      })
+    : null;
+
+  function swap(img) {
+    var entry = byHref[img.src];
+    if (!entry) return;
+    img.src = entry.src;
+    if (entry.full && upscaled) {
+      img.setAttribute('data-reg-full', entry.full);
+      upscaled.observe(img);
+    }
+  }
+
+  function scan(node) {
+    if (node.nodeType !== 1) return;
+    if (node.tagName === 'IMG') swap(node);
+    var imgs = node.getElementsByTagName('img');
+    for (var i = 0; i < imgs.length; i++) swap(imgs[i]);
+  }
+
+  new MutationObserver(function (records) {
+    records.forEach(function (record) {
+      if (record.type === 'attributes') swap(record.target);
+      else record.addedNodes.forEach(scan);
+    });
+  }).observe(document.documentElement, {
+    childList: true,
+    subtree: true,
+    attributes: true,
+    attributeFilter: ['src'],
+  });
+})();
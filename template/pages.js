@@ -0,0 +1,82 @@
+// Loads the sidecar pages listed in `window['__reg__'].pages` (see
+// `paginate` in crates/reg_core/src/report.rs). Nothing is fetched until
+// asked for: `window['__reg_pages__'].next('passedItems')` fetches the next
+// page of that list, appends it to `window['__reg__'].passedItems` and
+// resolves with the new items (an empty array once the list is complete);
+// `all()` loads every remaining page. Each load also fires a `reg:page`
+// event on `document` with `{ list, items }`. Pages are fetched over HTTP,
+// so the report has to be served rather than opened from disk.
+//
+// The UI renders the lists it was booted with, so the built-in template's
+// "Load the rest" button lists what it loads next to it instead.
+(function () {
+  var report = window['__reg__'];
+  var loaded = {};
+  var queues = {};
+
+  function load(list) {
+    var pages = report.pages[list] || [];
+    var index = loaded[list] || 0;
+    if (index >= pages.length) return Promise.resolve([]);
+    return fetch(pages[index])
+      .then(function (res) {
+        if (!res.ok) throw new Error(pages[index] + ': HTTP ' + res.status);
+        return res.json();
+      })
+      .then(function (items) {
+        loaded[list] = index + 1;
+        report[list] = report[list].concat(items);
+        document.dispatchEvent(
+          new CustomEvent('reg:page', { detail: { list: list, items: items } })
+        );
+        return items;
+      });
+  }
+
+  // One request per list at a time, so pages append in order.
+  function next(list) {
+    var result = (queues[list] || Promise.resolve()).then(function () {
+      return load(list);
+    });
+    queues[list] = result.catch(function () {});
+    return result;
+  }
+
+  function rest(list) {
+    return next(list).then(function (items) {
+      return items.length ? rest(list) : undefined;
+    });
+  }
+
+  function all() {
+    return Promise.all(Object.keys(report.pages).map(rest));
+  }
+
+  window['__reg_pages__'] = { next: next, all: all };
+
+  var button = document.querySelector('[data-reg-load-pages]');
+  var target = document.querySelector('[data-reg-loaded-items]');
+  if (!button || !target) return;
+  document.addEventListener('reg:page', function (event) {
+    event.detail.items.forEach(function (item) {
+      var li = document.createElement('li');
+      li.textContent = item.from
+        ? item.from.raw + ' \u2192 ' + item.to.raw
+        : item.raw;
+      li.setAttribute('data-list', event.detail.list);
+      target.appendChild(li);
+    });
+  });
+  button.addEventListener('click', function () {
+    button.disabled = true;
+    all().then(
+      function () {
+        button.remove();
+      },
+      function (error) {
+        button.disabled = false;
+        button.textContent = 'Load the rest (' + error.message + ')';
+      }
+    );
+  });
+})();